@@ -0,0 +1,4 @@
+pub mod icmpv4;
+pub mod parse;
+pub mod tcp;
+pub mod tun_interface;
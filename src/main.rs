@@ -1,23 +1,22 @@
-use parse::icmpv4_slice::Icmpv4Slice;
-use parse::ipv4_header_slice::Ipv4HeaderSlice;
-use parse::protocol::Protocol;
-
-use crate::parse::tcp_slice::TcpHeaderSlice;
-use crate::parse::ipv4::{IpPayload, Ipv4Packet};
-use crate::tcp::TcpConnManager;
-use crate::tun_interface::TunInterface;
-use icmpv4::process_icmpv4;
-
-mod icmpv4;
-mod parse;
-mod tcp;
-mod tun_interface;
+use rustcp::icmpv4::process_icmpv4;
+use rustcp::parse::icmpv4_slice::Icmpv4Slice;
+use rustcp::parse::ipv4::{IpPayload, Ipv4Packet};
+use rustcp::parse::ipv4_header_slice::Ipv4HeaderSlice;
+use rustcp::parse::protocol::Protocol;
+use rustcp::parse::tcp_slice::TcpHeaderSlice;
+use rustcp::tcp::{Quad, TcpConnManager, TcpEvent};
+use rustcp::tun_interface::{TunInterface, Tx};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Instant;
 
 // TODO: Clean up magical numbers
 // TODO: Add proper checks for protocols we dont support
 // TODO: Add better error messages when we are unable to create a type of packet
 // TODO: We seriously need much better error messages
 
+const ECHO_PORT: u16 = 7;
+const ECHO_BACKLOG: usize = 128;
+
 struct Processor {
     tcp_manager: TcpConnManager,
 }
@@ -39,9 +38,10 @@ impl Processor {
                 Some(IpPayload::Icmp(reply))
             }
             Protocol::Tcp => {
+                // replies are queued on the manager and sent by `transmit`
                 let tcp = TcpHeaderSlice::from_buf(ip.payload())?;
-                let reply = self.tcp_manager.process_packet(ip, &tcp)?;
-                Some(IpPayload::Tcp(reply))
+                self.tcp_manager.process_packet(ip, &tcp);
+                None
             }
             Protocol::Udp | Protocol::Unsupported => {
                 println!("Protocol: {:?} not supported", ip.protocol());
@@ -51,32 +51,102 @@ impl Processor {
 
         Some(Ipv4Packet::new(ip.reply(), payload))
     }
+
+    fn handle_tcp_events(&mut self) {
+        while let Some(event) = self.tcp_manager.poll_event() {
+            println!("{:?}", event);
+
+            match event {
+                TcpEvent::Acceptable(local) => {
+                    while let Ok(quad) = self.tcp_manager.accept(&local) {
+                        println!("Accepted: {:?}", quad);
+                    }
+                }
+                TcpEvent::Readable(quad) => self.echo(&quad),
+                // we have nothing more to say once the peer is done
+                TcpEvent::PeerClosed(quad) => self.tcp_manager.close(&quad),
+                _ => {}
+            }
+        }
+    }
+
+    // sends everything the peer wrote straight back to it
+    fn echo(&mut self, quad: &Quad) {
+        let mut buf = [0; 1500];
+
+        while let Ok(len) = self.tcp_manager.read(quad, &mut buf) {
+            if len == 0 {
+                break;
+            }
+
+            println!("recieved: {:?}", String::from_utf8_lossy(&buf[..len]));
+
+            match self.tcp_manager.write(quad, &buf[..len]) {
+                Ok(written) if written < len => {
+                    println!("send buffer full, dropped {} bytes", len - written)
+                }
+                Ok(_) => {}
+                Err(error) => println!("Error writing to connection: {}", error),
+            }
+        }
+    }
+
+    fn transmit(&mut self, tx: &Tx<'_>) {
+        while let Some(segment) = self.tcp_manager.poll_transmit() {
+            if let Err(error) = tx.send(&segment.to_packet()) {
+                println!("Error sending segment: {}", error);
+            }
+        }
+    }
 }
 
 fn main() {
     let mut interface = TunInterface::new();
     let mut processor = Processor::new();
-    println!("Starting to get data");
+    let echo = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, ECHO_PORT);
+    if let Err(error) = processor.tcp_manager.listen(echo, ECHO_BACKLOG) {
+        println!("Error listening on {}: {}", echo, error);
+    }
 
-    loop {
-        let result = interface.recv();
+    // optionally act as a client, e.g. `rustcp 10.0.0.1:8080`
+    if let Some(remote) = std::env::args().nth(1) {
+        let remote: SocketAddrV4 = remote.parse().expect("Expected a remote address");
+        match processor.tcp_manager.connect(remote) {
+            Ok(quad) => println!("Connecting: {:?}", quad),
+            Err(error) => println!("Error connecting to {}: {}", remote, error),
+        }
+    }
 
-        match result {
-            Ok((ip_packet, tx)) => {
-                let Some(response) = processor.process_ipv4(&ip_packet) else {
-                    continue;
-                };
+    println!("Starting to get data");
 
-                if let Err(error) = tx.send(&response) {
-                    println!("Error sending packet: {}", error);
-                    continue;
-                };
+    loop {
+        let timeout = processor
+            .tcp_manager
+            .next_timeout()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()));
 
-                println!("succesfully sent response");
-            }
+        match interface.wait(timeout) {
+            Ok(true) => match interface.recv() {
+                Ok((ip_packet, tx)) => {
+                    if let Some(response) = processor.process_ipv4(&ip_packet) {
+                        match tx.send(&response) {
+                            Ok(()) => println!("succesfully sent response"),
+                            Err(error) => println!("Error sending packet: {}", error),
+                        }
+                    }
+                }
+                Err(error) => {
+                    println!("Error recieving packet: {}", error);
+                }
+            },
+            Ok(false) => {}
             Err(error) => {
-                println!("Error recieving packet: {}", error);
+                println!("Error waiting for packet: {}", error);
             }
         }
+
+        processor.tcp_manager.on_timer();
+        processor.handle_tcp_events();
+        processor.transmit(&interface.tx());
     }
 }
@@ -14,4 +14,6 @@ pub mod icmpv4_slice;
 
 pub mod tcp;
 
+pub mod tcp_option;
+
 pub mod tcp_slice;
@@ -1,4 +1,5 @@
 use crate::parse::protocol::Protocol;
+use crate::parse::tcp_option::{MAX_TCP_OPTIONS_LENGTH, TcpOption, options_length};
 use crate::parse::utils::{
     ones_complement_sum, u16_from_buf_unchecked, u16_to_buf_unchecked, u32_to_buf_unchecked,
 };
@@ -25,7 +26,8 @@ pub struct TcpHeader<'a> {
     pub window: u16,
     pub psuedo_header: PsuedoHeader,
     pub urgent_pointer: u16,
-    pub options: &'a [u8],
+    /// padded with End of Option List bytes to a 4 byte boundary when written
+    pub options: &'a [TcpOption<'a>],
     pub data: &'a [u8],
 }
 
@@ -54,13 +56,14 @@ impl TcpHeader<'_> {
     // FIXME: let us have this return a error
     // TODO: need to have this sudo header shit
     pub fn to_buf(&self, buf: &mut [u8]) {
-        let tcp_size = MIN_TCP_HEADER_LENGTH + self.options.len() + self.data.len();
+        let options_length = options_length(self.options);
+        let tcp_size = MIN_TCP_HEADER_LENGTH + options_length + self.data.len();
 
-        if buf.len() < (tcp_size) {
+        if buf.len() < (tcp_size) || options_length > MAX_TCP_OPTIONS_LENGTH {
             return;
         }
 
-        let data_offset = (MIN_TCP_HEADER_LENGTH + self.options.len()) / 4;
+        let data_offset = (MIN_TCP_HEADER_LENGTH + options_length) / 4;
         unsafe {
             u16_to_buf_unchecked(buf, 0, self.src_port);
 
@@ -91,13 +94,14 @@ impl TcpHeader<'_> {
 
         let mut cur_index = MIN_TCP_HEADER_LENGTH;
 
-        for val in self.options.iter() {
-            unsafe {
-                *buf.get_unchecked_mut(cur_index) = *val;
-                cur_index += 1;
-            }
+        for option in self.options.iter() {
+            cur_index += option.to_buf(&mut buf[cur_index..]);
         }
 
+        // pad with End of Option List so the data starts on a 32 bit word
+        buf[cur_index..MIN_TCP_HEADER_LENGTH + options_length].fill(0);
+        cur_index = MIN_TCP_HEADER_LENGTH + options_length;
+
         for val in self.data.iter() {
             unsafe {
                 *buf.get_unchecked_mut(cur_index) = *val;
@@ -136,7 +140,7 @@ impl TcpHeader<'_> {
 
     /// returns the total length of the TCP Header
     pub fn length(&self) -> usize {
-        MIN_TCP_HEADER_LENGTH + self.options.len() + self.data.len()
+        MIN_TCP_HEADER_LENGTH + options_length(self.options) + self.data.len()
     }
 }
 
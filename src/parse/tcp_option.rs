@@ -0,0 +1,215 @@
+use crate::parse::utils::{
+    u16_from_buf_unchecked, u16_to_buf_unchecked, u32_from_buf_unchecked, u32_to_buf_unchecked,
+};
+
+pub const MAX_TCP_OPTIONS_LENGTH: usize = 40;
+
+pub const END_OF_LIST_KIND: u8 = 0;
+pub const NO_OPERATION_KIND: u8 = 1;
+pub const MAX_SEGMENT_SIZE_KIND: u8 = 2;
+pub const WINDOW_SCALE_KIND: u8 = 3;
+pub const SACK_PERMITTED_KIND: u8 = 4;
+pub const SACK_KIND: u8 = 5;
+pub const TIMESTAMPS_KIND: u8 = 8;
+
+/// a SACK option holds at most 4 blocks in the 40 bytes of option space
+pub const MAX_SACK_BLOCKS: usize = 4;
+
+/// The edges of the blocks in a SACK option (RFC 2018). Each block is the
+/// sequence number of its first byte and of the byte just after its last.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SackBlocks {
+    blocks: [(u32, u32); MAX_SACK_BLOCKS],
+    len: usize,
+}
+
+impl SackBlocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a block, returns false if the option is already full
+    pub fn push(&mut self, left: u32, right: u32) -> bool {
+        if self.len == MAX_SACK_BLOCKS {
+            return false;
+        }
+
+        self.blocks[self.len] = (left, right);
+        self.len += 1;
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.blocks[..self.len].iter().copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpOption<'a> {
+    EndOfList,
+    NoOperation,
+    MaxSegmentSize(u16),
+    /// shift count for the window, only valid on SYNs (RFC 7323)
+    WindowScale(u8),
+    SackPermitted,
+    Sack(SackBlocks),
+    Timestamps {
+        value: u32,
+        echo_reply: u32,
+    },
+    /// any option we do not understand, kept as its kind and its payload
+    Unknown(u8, &'a [u8]),
+}
+
+impl TcpOption<'_> {
+    /// number of bytes the option takes up in the header
+    pub fn length(&self) -> usize {
+        match self {
+            TcpOption::EndOfList | TcpOption::NoOperation => 1,
+            TcpOption::MaxSegmentSize(_) => 4,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::SackPermitted => 2,
+            TcpOption::Sack(blocks) => 2 + 8 * blocks.len(),
+            TcpOption::Timestamps { .. } => 10,
+            TcpOption::Unknown(_, data) => 2 + data.len(),
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            TcpOption::EndOfList => END_OF_LIST_KIND,
+            TcpOption::NoOperation => NO_OPERATION_KIND,
+            TcpOption::MaxSegmentSize(_) => MAX_SEGMENT_SIZE_KIND,
+            TcpOption::WindowScale(_) => WINDOW_SCALE_KIND,
+            TcpOption::SackPermitted => SACK_PERMITTED_KIND,
+            TcpOption::Sack(_) => SACK_KIND,
+            TcpOption::Timestamps { .. } => TIMESTAMPS_KIND,
+            TcpOption::Unknown(kind, _) => *kind,
+        }
+    }
+
+    /// Writes the option to the front of `buf` and returns its length.
+    /// Panics if `buf` is shorter than `length()`.
+    pub fn to_buf(&self, buf: &mut [u8]) -> usize {
+        let len = self.length();
+        assert!(
+            buf.len() >= len,
+            "buffer is not large enough for TCP option"
+        );
+
+        buf[0] = self.kind();
+        if len == 1 {
+            return 1;
+        }
+        buf[1] = len as u8;
+
+        unsafe {
+            match self {
+                TcpOption::MaxSegmentSize(mss) => u16_to_buf_unchecked(buf, 2, *mss),
+                TcpOption::WindowScale(shift) => *buf.get_unchecked_mut(2) = *shift,
+                TcpOption::Sack(blocks) => {
+                    for (i, (left, right)) in blocks.iter().enumerate() {
+                        u32_to_buf_unchecked(buf, 2 + i * 8, left);
+                        u32_to_buf_unchecked(buf, 6 + i * 8, right);
+                    }
+                }
+                TcpOption::Timestamps { value, echo_reply } => {
+                    u32_to_buf_unchecked(buf, 2, *value);
+                    u32_to_buf_unchecked(buf, 6, *echo_reply);
+                }
+                TcpOption::Unknown(_, data) => buf[2..len].copy_from_slice(data),
+                _ => {}
+            }
+        }
+
+        len
+    }
+}
+
+/// Length of `options` once padded out to a multiple of 4 bytes
+pub fn options_length(options: &[TcpOption<'_>]) -> usize {
+    options
+        .iter()
+        .map(TcpOption::length)
+        .sum::<usize>()
+        .next_multiple_of(4)
+}
+
+/// Iterates over the options of a TCP header. Iteration stops after an
+/// End of Option List or at the first option with a bad length.
+pub struct TcpOptionIter<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> TcpOptionIter<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+}
+
+impl<'a> Iterator for TcpOptionIter<'a> {
+    type Item = TcpOption<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kind = *self.buf.first()?;
+
+        match kind {
+            END_OF_LIST_KIND => {
+                self.buf = &[];
+                return Some(TcpOption::EndOfList);
+            }
+            NO_OPERATION_KIND => {
+                self.buf = &self.buf[1..];
+                return Some(TcpOption::NoOperation);
+            }
+            _ => {}
+        }
+
+        let len = usize::from(*self.buf.get(1)?);
+        if len < 2 || len > self.buf.len() {
+            self.buf = &[];
+            return None;
+        }
+
+        let option = &self.buf[..len];
+        let data = &option[2..];
+        self.buf = &self.buf[len..];
+
+        let option = unsafe {
+            match (kind, len) {
+                (MAX_SEGMENT_SIZE_KIND, 4) => {
+                    TcpOption::MaxSegmentSize(u16_from_buf_unchecked(option, 2))
+                }
+                (WINDOW_SCALE_KIND, 3) => TcpOption::WindowScale(*option.get_unchecked(2)),
+                (SACK_PERMITTED_KIND, 2) => TcpOption::SackPermitted,
+                (SACK_KIND, _)
+                    if data.len().is_multiple_of(8) && data.len() / 8 <= MAX_SACK_BLOCKS =>
+                {
+                    let mut blocks = SackBlocks::new();
+                    for block in data.chunks_exact(8) {
+                        blocks.push(
+                            u32_from_buf_unchecked(block, 0),
+                            u32_from_buf_unchecked(block, 4),
+                        );
+                    }
+                    TcpOption::Sack(blocks)
+                }
+                (TIMESTAMPS_KIND, 10) => TcpOption::Timestamps {
+                    value: u32_from_buf_unchecked(option, 2),
+                    echo_reply: u32_from_buf_unchecked(option, 6),
+                },
+                _ => TcpOption::Unknown(kind, data),
+            }
+        };
+
+        Some(option)
+    }
+}
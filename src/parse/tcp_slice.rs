@@ -1,4 +1,5 @@
 use crate::parse::tcp::MIN_TCP_HEADER_LENGTH;
+use crate::parse::tcp_option::TcpOptionIter;
 use crate::parse::utils::{u16_from_buf_unchecked, u32_from_buf_unchecked};
 use std::fmt::{self, Debug, Formatter};
 
@@ -14,6 +15,10 @@ impl<'a> TcpHeaderSlice<'a> {
 
         let data_offset = unsafe { *buf.get_unchecked(12) >> 4 };
 
+        if usize::from(data_offset * 4) < MIN_TCP_HEADER_LENGTH {
+            return None;
+        }
+
         if buf.len() < usize::from(data_offset * 4) {
             return None;
         }
@@ -86,14 +91,19 @@ impl<'a> TcpHeaderSlice<'a> {
         unsafe { u16_from_buf_unchecked(self.buf, 18) }
     }
 
-    fn options(&self) -> &'a [u8] {
+    pub fn options(&self) -> &'a [u8] {
         let data_offset = self.data_offset();
         let data_offset = usize::from(data_offset * 4);
 
         &self.buf[MIN_TCP_HEADER_LENGTH..data_offset]
     }
 
-    fn data(&self) -> &'a [u8] {
+    /// the options parsed into `TcpOption`s
+    pub fn options_iter(&self) -> TcpOptionIter<'a> {
+        TcpOptionIter::new(self.options())
+    }
+
+    pub fn data(&self) -> &'a [u8] {
         let data_offset = unsafe { *self.buf.get_unchecked(12) >> 4 };
         let data_offset = usize::from(data_offset) * 4;
 
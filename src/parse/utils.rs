@@ -1,11 +1,13 @@
-// If index and index + 1 are out of bounds then this
-// will lead to undefined behavior.
+/// # Safety
+/// If index and index + 1 are out of bounds then this
+/// will lead to undefined behavior.
 pub unsafe fn u16_from_buf_unchecked(buf: &[u8], index: usize) -> u16 {
     unsafe { ((*buf.get_unchecked(index) as u16) << 8) | *buf.get_unchecked(index + 1) as u16 }
 }
 
-// If index up to index + 3 are out of bounds then this
-// will lead to undefined behavior
+/// # Safety
+/// If index up to index + 3 are out of bounds then this
+/// will lead to undefined behavior
 pub unsafe fn u32_from_buf_unchecked(buf: &[u8], index: usize) -> u32 {
     let mut total = 0;
     for offset in 0..4 {
@@ -17,8 +19,9 @@ pub unsafe fn u32_from_buf_unchecked(buf: &[u8], index: usize) -> u32 {
     total
 }
 
-// If index and index + 1 are outside of the buffers length
-// then this will lead to undetermined behavior
+/// # Safety
+/// If index and index + 1 are outside of the buffers length
+/// then this will lead to undetermined behavior
 pub unsafe fn u16_to_buf_unchecked(buf: &mut [u8], index: usize, val: u16) {
     unsafe {
         *buf.get_unchecked_mut(index) = (val >> 8) as u8;
@@ -26,8 +29,9 @@ pub unsafe fn u16_to_buf_unchecked(buf: &mut [u8], index: usize, val: u16) {
     }
 }
 
-// If index..index + 3 are outside of the buffers length
-// then this will lead to undetermined behavior
+/// # Safety
+/// If index..index + 3 are outside of the buffers length
+/// then this will lead to undetermined behavior
 pub unsafe fn u32_to_buf_unchecked(buf: &mut [u8], index: usize, val: u32) {
     for i in 0..4 {
         let shift = 24 - (i * 8);
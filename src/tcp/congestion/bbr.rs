@@ -0,0 +1,385 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::tcp::congestion::{AckInfo, CongestionControl, RateSample, initial_window};
+
+/// 2/ln(2), the smallest gain that doubles the sending rate every round
+const HIGH_GAIN: f64 = 2.885;
+/// pacing gains cycled through in ProbeBW, one phase per round trip
+const PROBE_BW_GAINS: [f64; 8] = [1.25, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+const PROBE_BW_CWND_GAIN: f64 = 2.0;
+/// round trips the bottleneck bandwidth filter remembers
+const BTL_BW_FILTER_ROUNDS: u64 = 10;
+/// how long a min RTT sample stays valid before ProbeRTT looks for a new one
+const MIN_RTT_WINDOW: Duration = Duration::from_secs(10);
+const PROBE_RTT_DURATION: Duration = Duration::from_millis(200);
+/// segments in flight during ProbeRTT and the smallest window otherwise
+const MIN_CWND_SEGMENTS: usize = 4;
+/// startup ends once the bandwidth stops growing by this much...
+const FULL_BW_GROWTH: f64 = 1.25;
+/// ...for this many rounds in a row
+const FULL_BW_ROUNDS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BbrMode {
+    /// doubles the sending rate every round to find the bottleneck
+    Startup,
+    /// drains the queue startup built up
+    Drain,
+    /// cycles the pacing rate around the estimated bandwidth
+    ProbeBw,
+    /// briefly cuts what is in flight to measure the round trip time
+    /// without our own queue
+    ProbeRtt,
+}
+
+/// Keeps the largest value seen over the last few rounds
+#[derive(Debug, Default)]
+struct MaxFilter {
+    /// decreasing values, each with the round it was seen in
+    samples: VecDeque<(u64, f64)>,
+}
+
+impl MaxFilter {
+    fn update(&mut self, round: u64, value: f64) {
+        while self
+            .samples
+            .back()
+            .is_some_and(|&(_, sample)| sample <= value)
+        {
+            self.samples.pop_back();
+        }
+        self.samples.push_back((round, value));
+
+        while self
+            .samples
+            .front()
+            .is_some_and(|&(seen, _)| seen + BTL_BW_FILTER_ROUNDS <= round)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    fn max(&self) -> f64 {
+        self.samples.front().map_or(0.0, |&(_, value)| value)
+    }
+}
+
+/// A BBR (version 1) style controller. It builds a model of the path from
+/// delivery rate samples, the bottleneck bandwidth and the round trip
+/// propagation time, and paces at the estimated bandwidth instead of
+/// reacting to every loss.
+#[derive(Debug)]
+pub struct Bbr {
+    mss: usize,
+    mode: BbrMode,
+    cwnd: usize,
+    btl_bw: MaxFilter,
+    min_rtt: Option<Duration>,
+    min_rtt_stamp: Instant,
+    pacing_gain: f64,
+    cwnd_gain: f64,
+    /// round trips counted by delivered data
+    round_count: u64,
+    /// a round ends when data sent after this much was delivered is acked
+    next_round_delivered: u64,
+    /// bandwidth startup last saw grow by `FULL_BW_GROWTH`
+    full_bw: f64,
+    full_bw_rounds: u32,
+    filled_pipe: bool,
+    /// phase in `PROBE_BW_GAINS`
+    cycle_index: usize,
+    cycle_stamp: Instant,
+    probe_rtt_done: Option<Instant>,
+    probe_rtt_round_done: bool,
+    /// window to go back to after recovery or ProbeRTT
+    prior_cwnd: usize,
+    in_recovery: bool,
+}
+
+impl Bbr {
+    pub fn new() -> Self {
+        let now = Instant::now();
+
+        Self {
+            mss: 0,
+            mode: BbrMode::Startup,
+            cwnd: 0,
+            btl_bw: MaxFilter::default(),
+            min_rtt: None,
+            min_rtt_stamp: now,
+            pacing_gain: HIGH_GAIN,
+            cwnd_gain: HIGH_GAIN,
+            round_count: 0,
+            next_round_delivered: 0,
+            full_bw: 0.0,
+            full_bw_rounds: 0,
+            filled_pipe: false,
+            cycle_index: 0,
+            cycle_stamp: now,
+            probe_rtt_done: None,
+            probe_rtt_round_done: false,
+            prior_cwnd: 0,
+            in_recovery: false,
+        }
+    }
+
+    pub fn mode(&self) -> BbrMode {
+        self.mode
+    }
+
+    /// estimated bottleneck bandwidth in bytes per second
+    pub fn btl_bw(&self) -> f64 {
+        self.btl_bw.max()
+    }
+
+    /// estimated round trip propagation time
+    pub fn min_rtt(&self) -> Option<Duration> {
+        self.min_rtt
+    }
+
+    fn min_cwnd(&self) -> usize {
+        MIN_CWND_SEGMENTS * self.mss
+    }
+
+    /// bandwidth-delay product scaled by `gain`
+    fn inflight(&self, gain: f64) -> Option<usize> {
+        let min_rtt = self.min_rtt?;
+        let bdp = self.btl_bw() * min_rtt.as_secs_f64();
+        Some((gain * bdp) as usize)
+    }
+
+    fn update_round(&mut self, rate: &RateSample) -> bool {
+        if rate.prior_delivered < self.next_round_delivered {
+            return false;
+        }
+
+        self.next_round_delivered = rate.prior_delivered + rate.delivered;
+        self.round_count += 1;
+        true
+    }
+
+    fn update_btl_bw(&mut self, rate: &RateSample) {
+        let delivery_rate = rate.delivery_rate();
+        // an app limited sample only tells us the path is at least this fast
+        if !rate.app_limited || delivery_rate >= self.btl_bw() {
+            self.btl_bw.update(self.round_count, delivery_rate);
+        }
+    }
+
+    fn check_full_pipe(&mut self, round_start: bool, rate: &RateSample) {
+        if self.filled_pipe || !round_start || rate.app_limited {
+            return;
+        }
+
+        if self.btl_bw() >= self.full_bw * FULL_BW_GROWTH {
+            self.full_bw = self.btl_bw();
+            self.full_bw_rounds = 0;
+            return;
+        }
+
+        self.full_bw_rounds += 1;
+        if self.full_bw_rounds >= FULL_BW_ROUNDS {
+            self.filled_pipe = true;
+        }
+    }
+
+    fn enter_probe_bw(&mut self, now: Instant) {
+        self.mode = BbrMode::ProbeBw;
+        self.cwnd_gain = PROBE_BW_CWND_GAIN;
+        // start anywhere but the draining phase
+        self.cycle_index =
+            (now - self.min_rtt_stamp).subsec_nanos() as usize % (PROBE_BW_GAINS.len() - 1);
+        if self.cycle_index >= 1 {
+            self.cycle_index += 1;
+        }
+        self.pacing_gain = PROBE_BW_GAINS[self.cycle_index];
+        self.cycle_stamp = now;
+    }
+
+    /// `min_rtt_expired` says the min RTT filter had gone stale before this
+    /// ACK's sample was taken
+    fn update_mode(&mut self, ack: &AckInfo, min_rtt_expired: bool) {
+        let now = ack.now;
+
+        match self.mode {
+            BbrMode::Startup if self.filled_pipe => {
+                self.mode = BbrMode::Drain;
+                self.pacing_gain = 1.0 / HIGH_GAIN;
+                self.cwnd_gain = HIGH_GAIN;
+            }
+            BbrMode::Drain if self.inflight(1.0).is_some_and(|bdp| ack.in_flight <= bdp) => {
+                self.enter_probe_bw(now);
+            }
+            BbrMode::ProbeBw => {
+                let min_rtt = self.min_rtt.unwrap_or_default();
+                let elapsed = now - self.cycle_stamp > min_rtt;
+                let full_length = match self.pacing_gain {
+                    // probing up ends once the extra data is in flight
+                    gain if gain > 1.0 => {
+                        elapsed
+                            && self
+                                .inflight(gain)
+                                .is_some_and(|target| ack.in_flight >= target)
+                    }
+                    // draining ends early once the queue is gone
+                    gain if gain < 1.0 => {
+                        elapsed || self.inflight(1.0).is_some_and(|bdp| ack.in_flight <= bdp)
+                    }
+                    _ => elapsed,
+                };
+
+                if full_length {
+                    self.cycle_index = (self.cycle_index + 1) % PROBE_BW_GAINS.len();
+                    self.pacing_gain = PROBE_BW_GAINS[self.cycle_index];
+                    self.cycle_stamp = now;
+                }
+            }
+            _ => {}
+        }
+
+        // the min RTT sample went stale, drain the pipe to get a new one
+        if self.mode != BbrMode::ProbeRtt && min_rtt_expired {
+            self.mode = BbrMode::ProbeRtt;
+            self.pacing_gain = 1.0;
+            self.cwnd_gain = 1.0;
+            self.prior_cwnd = self.prior_cwnd.max(self.cwnd);
+            self.probe_rtt_done = None;
+        }
+
+        if self.mode == BbrMode::ProbeRtt {
+            self.handle_probe_rtt(ack);
+        }
+    }
+
+    fn handle_probe_rtt(&mut self, ack: &AckInfo) {
+        let now = ack.now;
+
+        match self.probe_rtt_done {
+            None if ack.in_flight <= self.min_cwnd() => {
+                self.probe_rtt_done = Some(now + PROBE_RTT_DURATION);
+                self.probe_rtt_round_done = false;
+                self.next_round_delivered = 0;
+            }
+            Some(done) => {
+                if ack
+                    .rate
+                    .is_some_and(|rate| rate.prior_delivered >= self.next_round_delivered)
+                {
+                    self.probe_rtt_round_done = true;
+                }
+
+                if self.probe_rtt_round_done && now >= done {
+                    self.min_rtt_stamp = now;
+                    self.cwnd = self.cwnd.max(self.prior_cwnd);
+                    self.prior_cwnd = 0;
+
+                    if self.filled_pipe {
+                        self.enter_probe_bw(now);
+                    } else {
+                        self.mode = BbrMode::Startup;
+                        self.pacing_gain = HIGH_GAIN;
+                        self.cwnd_gain = HIGH_GAIN;
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn update_cwnd(&mut self, ack: &AckInfo) {
+        let target = self
+            .inflight(self.cwnd_gain)
+            .unwrap_or(self.cwnd)
+            .max(self.min_cwnd());
+
+        if self.in_recovery {
+            // packet conservation, send one segment for each one delivered
+            self.cwnd = self.cwnd.max(ack.in_flight + ack.acked);
+        } else if self.filled_pipe {
+            self.cwnd = (self.cwnd + ack.acked).min(target);
+        } else if self.cwnd < target || ack.rate.is_none() {
+            self.cwnd += ack.acked;
+        }
+
+        self.cwnd = self.cwnd.max(self.min_cwnd());
+        if self.mode == BbrMode::ProbeRtt {
+            self.cwnd = self.cwnd.min(self.min_cwnd());
+        }
+    }
+}
+
+impl Default for Bbr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CongestionControl for Bbr {
+    fn set_mss(&mut self, mss: usize) {
+        self.mss = mss;
+        self.cwnd = initial_window(mss);
+    }
+
+    fn window(&self) -> usize {
+        self.cwnd
+    }
+
+    fn on_ack(&mut self, ack: &AckInfo) {
+        // a stale filter takes any sample, ProbeRTT is entered below to
+        // make sure the next one is taken with the queue drained
+        let min_rtt_expired = ack.now - self.min_rtt_stamp > MIN_RTT_WINDOW;
+        if let Some(rtt) = ack.rtt.or(ack.rate.map(|rate| rate.rtt))
+            && (self.min_rtt.is_none_or(|min_rtt| rtt <= min_rtt) || min_rtt_expired)
+        {
+            self.min_rtt = Some(rtt);
+            self.min_rtt_stamp = ack.now;
+        }
+
+        if let Some(rate) = &ack.rate {
+            let round_start = self.update_round(rate);
+            self.update_btl_bw(rate);
+            self.check_full_pipe(round_start, rate);
+        }
+
+        self.update_mode(ack, min_rtt_expired);
+        self.update_cwnd(ack);
+    }
+
+    fn on_duplicate_ack(&mut self, ack: &AckInfo) {
+        // SACKed data still says how fast the path delivers
+        if let Some(rate) = &ack.rate {
+            let round_start = self.update_round(rate);
+            self.update_btl_bw(rate);
+            self.check_full_pipe(round_start, rate);
+        }
+    }
+
+    fn pacing_rate(&self) -> Option<f64> {
+        let btl_bw = self.btl_bw();
+        if btl_bw > 0.0 {
+            return Some(self.pacing_gain * btl_bw);
+        }
+
+        // no bandwidth estimate yet, pace the initial window over one round trip
+        let min_rtt = self.min_rtt?;
+        Some(HIGH_GAIN * self.cwnd as f64 / min_rtt.as_secs_f64().max(1e-3))
+    }
+
+    fn on_congestion_event(&mut self, in_flight: usize, _now: Instant) {
+        self.prior_cwnd = self.cwnd;
+        self.in_recovery = true;
+        self.cwnd = in_flight.max(self.min_cwnd());
+    }
+
+    fn on_recovery_end(&mut self, _in_flight: usize) {
+        self.in_recovery = false;
+        self.cwnd = self.cwnd.max(self.prior_cwnd);
+    }
+
+    fn on_rto(&mut self, _in_flight: usize) {
+        self.prior_cwnd = self.cwnd;
+        self.in_recovery = false;
+        self.cwnd = self.mss;
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::{Duration, Instant};
+
+use crate::tcp::congestion::{AckInfo, CongestionControl, initial_window};
+
+/// C from RFC 9438, how aggressively the window grows
+const C: f64 = 0.4;
+/// multiplicative decrease factor
+const BETA: f64 = 0.7;
+/// additive increase of the Reno-friendly estimate before it reaches the
+/// window we had when the last congestion event happened
+const ALPHA: f64 = 3.0 * (1.0 - BETA) / (1.0 + BETA);
+
+/// CUBIC as described in RFC 9438. Windows are kept in segments, the
+/// cubic function is only meaningful with fractional growth.
+#[derive(Debug)]
+pub struct Cubic {
+    mss: usize,
+    cwnd: f64,
+    ssthresh: f64,
+    /// window just before the last reduction, lowered by fast convergence
+    /// when the previous reduction happened at a larger window
+    w_max: f64,
+    /// cwnd before the last reduction, `cwnd_prior` in the RFC
+    cwnd_prior: f64,
+    /// time the cubic function takes to grow back to `w_max`
+    k: Duration,
+    /// start of the current congestion avoidance stage
+    epoch_start: Option<Instant>,
+    /// estimate of the window Reno would have, `W_est` in the RFC
+    w_est: f64,
+    /// latest round trip time sample
+    rtt: Duration,
+}
+
+impl Cubic {
+    pub fn new() -> Self {
+        Self {
+            mss: 0,
+            cwnd: 0.0,
+            ssthresh: f64::INFINITY,
+            w_max: 0.0,
+            cwnd_prior: 0.0,
+            k: Duration::ZERO,
+            epoch_start: None,
+            w_est: 0.0,
+            rtt: Duration::ZERO,
+        }
+    }
+
+    /// W_max in bytes
+    pub fn w_max(&self) -> usize {
+        (self.w_max * self.mss as f64) as usize
+    }
+
+    pub fn k(&self) -> Duration {
+        self.k
+    }
+
+    /// `None` outside of congestion avoidance, the epoch starts with the
+    /// first ACK after slow start or recovery
+    pub fn epoch_start(&self) -> Option<Instant> {
+        self.epoch_start
+    }
+
+    fn start_epoch(&mut self, now: Instant) {
+        self.epoch_start = Some(now);
+        self.w_est = self.cwnd;
+
+        if self.cwnd < self.w_max {
+            self.k = Duration::from_secs_f64(((self.w_max - self.cwnd) / C).cbrt());
+        } else {
+            self.k = Duration::ZERO;
+            self.w_max = self.cwnd;
+        }
+    }
+
+    /// W_cubic(t) from RFC 9438 section 4.2, `t` in seconds since the
+    /// epoch started
+    fn w_cubic(&self, t: f64) -> f64 {
+        C * (t - self.k.as_secs_f64()).powi(3) + self.w_max
+    }
+
+    /// multiplicative decrease with fast convergence, RFC 9438 section 4.6
+    /// and 4.7
+    fn reduce(&mut self) {
+        self.epoch_start = None;
+        self.cwnd_prior = self.cwnd;
+
+        self.w_max = if self.cwnd < self.w_max {
+            self.cwnd * (1.0 + BETA) / 2.0
+        } else {
+            self.cwnd
+        };
+
+        self.ssthresh = (self.cwnd * BETA).max(2.0);
+    }
+}
+
+impl Default for Cubic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CongestionControl for Cubic {
+    fn set_mss(&mut self, mss: usize) {
+        self.mss = mss;
+        self.cwnd = (initial_window(mss) / mss) as f64;
+    }
+
+    fn window(&self) -> usize {
+        (self.cwnd * self.mss as f64) as usize
+    }
+
+    fn on_ack(&mut self, ack: &AckInfo) {
+        if let Some(rtt) = ack.rtt {
+            self.rtt = rtt;
+        }
+
+        if ack.in_recovery || self.mss == 0 {
+            return;
+        }
+
+        let acked = ack.acked as f64 / self.mss as f64;
+
+        if self.cwnd < self.ssthresh {
+            self.cwnd += acked.min(1.0);
+            return;
+        }
+
+        let epoch_start = match self.epoch_start {
+            Some(epoch_start) => epoch_start,
+            None => {
+                self.start_epoch(ack.now);
+                ack.now
+            }
+        };
+        let t = (ack.now - epoch_start).as_secs_f64();
+
+        // Reno-friendly region, never grow slower than Reno would
+        let alpha = if self.w_est >= self.cwnd_prior {
+            1.0
+        } else {
+            ALPHA
+        };
+        self.w_est += alpha * acked / self.cwnd;
+
+        if self.w_cubic(t) < self.w_est {
+            self.cwnd = self.w_est;
+            return;
+        }
+
+        // aim for where the curve is one round trip from now
+        let target = self
+            .w_cubic(t + self.rtt.as_secs_f64())
+            .clamp(self.cwnd, 1.5 * self.cwnd);
+        self.cwnd += (target - self.cwnd) / self.cwnd * acked;
+    }
+
+    fn on_congestion_event(&mut self, _in_flight: usize, _now: Instant) {
+        self.reduce();
+        self.cwnd = self.ssthresh;
+    }
+
+    fn on_recovery_end(&mut self, _in_flight: usize) {
+        self.cwnd = self.ssthresh;
+    }
+
+    fn on_rto(&mut self, _in_flight: usize) {
+        self.reduce();
+        self.cwnd = 1.0;
+    }
+}
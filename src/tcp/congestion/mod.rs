@@ -0,0 +1,120 @@
+use std::any::Any;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+pub use bbr::{Bbr, BbrMode};
+pub use cubic::Cubic;
+pub use newreno::NewReno;
+
+mod bbr;
+
+mod cubic;
+
+mod newreno;
+
+/// The algorithms that ship with the stack, used to pick the default for
+/// new connections in `TcpConfig`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CongestionAlgorithm {
+    #[default]
+    NewReno,
+    Cubic,
+    Bbr,
+}
+
+impl CongestionAlgorithm {
+    pub fn build(self) -> Box<dyn CongestionControl> {
+        match self {
+            CongestionAlgorithm::NewReno => Box::new(NewReno::new()),
+            CongestionAlgorithm::Cubic => Box::new(Cubic::new()),
+            CongestionAlgorithm::Bbr => Box::new(Bbr::new()),
+        }
+    }
+}
+
+/// How fast data was delivered over the interval an ACK covers
+#[derive(Debug, Clone, Copy)]
+pub struct RateSample {
+    /// bytes delivered during `interval`
+    pub delivered: u64,
+    pub interval: Duration,
+    /// bytes the connection had delivered when the newest segment the ACK
+    /// covers was sent, used to count round trips
+    pub prior_delivered: u64,
+    /// the application did not have enough data to fill the window, so the
+    /// rate says little about the path
+    pub app_limited: bool,
+    /// time from sending the newest delivered segment to its ACK
+    pub rtt: Duration,
+}
+
+impl RateSample {
+    /// bytes per second
+    pub fn delivery_rate(&self) -> f64 {
+        self.delivered as f64 / self.interval.as_secs_f64()
+    }
+}
+
+/// What an ACK told us, handed to `CongestionControl::on_ack` and
+/// `CongestionControl::on_duplicate_ack`
+#[derive(Debug, Clone, Copy)]
+pub struct AckInfo {
+    pub now: Instant,
+    /// bytes newly acknowledged
+    pub acked: usize,
+    /// bytes still in flight once the ACK has been processed
+    pub in_flight: usize,
+    /// round trip time measured by this ACK, if it gave one
+    pub rtt: Option<Duration>,
+    /// the ACK arrived during fast recovery
+    pub in_recovery: bool,
+    /// delivery rate sample, if the ACK acknowledged or SACKed anything
+    pub rate: Option<RateSample>,
+}
+
+/// Decides how much data a connection may have in flight. The connection
+/// detects duplicate ACKs, losses and timeouts and reports them here, the
+/// algorithm only has to adjust its window. A `&dyn CongestionControl` can
+/// be upcast to `&dyn Any` to get at the state of a specific algorithm.
+pub trait CongestionControl: Any + fmt::Debug {
+    /// Called once the MSS has been negotiated, before any data is sent
+    fn set_mss(&mut self, mss: usize);
+
+    /// bytes that may be in flight, the congestion window
+    fn window(&self) -> usize;
+
+    /// new data was acknowledged
+    fn on_ack(&mut self, ack: &AckInfo);
+
+    /// an ACK arrived that did not acknowledge anything new, though it may
+    /// have SACKed something
+    fn on_duplicate_ack(&mut self, _ack: &AckInfo) {}
+
+    /// bytes per second to spread segments out at, `None` sends them as
+    /// soon as the windows allow
+    fn pacing_rate(&self) -> Option<f64> {
+        None
+    }
+
+    /// Loss was detected by three duplicate ACKs or SACK and fast recovery
+    /// starts. `in_flight` is the amount of data outstanding.
+    fn on_congestion_event(&mut self, in_flight: usize, now: Instant);
+
+    /// everything that was outstanding when recovery started has been
+    /// acknowledged
+    fn on_recovery_end(&mut self, in_flight: usize);
+
+    /// the retransmission timer expired
+    fn on_rto(&mut self, in_flight: usize);
+}
+
+/// initial window from RFC 5681 section 3.1
+fn initial_window(mss: usize) -> usize {
+    if mss > 2190 {
+        2 * mss
+    } else if mss > 1095 {
+        3 * mss
+    } else {
+        4 * mss
+    }
+}
@@ -0,0 +1,84 @@
+use std::time::Instant;
+
+use crate::tcp::congestion::{AckInfo, CongestionControl, initial_window};
+
+/// Slow start and congestion avoidance from RFC 5681 with the NewReno
+/// changes to fast recovery from RFC 6582. The window is not inflated
+/// during recovery, the connection stops counting segments that duplicate
+/// ACKs or SACK blocks say have left the network instead.
+#[derive(Debug)]
+pub struct NewReno {
+    mss: usize,
+    cwnd: usize,
+    ssthresh: usize,
+    /// bytes acknowledged since the window last grew in congestion avoidance
+    bytes_acked: usize,
+}
+
+impl NewReno {
+    pub fn new() -> Self {
+        Self {
+            mss: 0,
+            cwnd: 0,
+            ssthresh: usize::MAX,
+            bytes_acked: 0,
+        }
+    }
+
+    /// RFC 5681 equation 4, half of what was in flight but at least two segments
+    fn reduce(&mut self, in_flight: usize) {
+        self.ssthresh = (in_flight / 2).max(2 * self.mss);
+        self.bytes_acked = 0;
+    }
+}
+
+impl Default for NewReno {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CongestionControl for NewReno {
+    fn set_mss(&mut self, mss: usize) {
+        self.mss = mss;
+        self.cwnd = initial_window(mss);
+    }
+
+    fn window(&self) -> usize {
+        self.cwnd
+    }
+
+    fn on_ack(&mut self, ack: &AckInfo) {
+        if ack.in_recovery {
+            return;
+        }
+
+        if self.cwnd < self.ssthresh {
+            // slow start, at most one segment per ACK
+            self.cwnd += ack.acked.min(self.mss);
+        } else {
+            // congestion avoidance, one segment per window's worth of ACKs
+            self.bytes_acked += ack.acked;
+            if self.bytes_acked >= self.cwnd {
+                self.bytes_acked -= self.cwnd;
+                self.cwnd += self.mss;
+            }
+        }
+    }
+
+    fn on_congestion_event(&mut self, in_flight: usize, _now: Instant) {
+        self.reduce(in_flight);
+        self.cwnd = self.ssthresh;
+    }
+
+    fn on_recovery_end(&mut self, in_flight: usize) {
+        // RFC 6582 option 1, do not let a burst out when little is in flight
+        self.cwnd = self.ssthresh.min(in_flight.max(self.mss) + self.mss);
+    }
+
+    fn on_rto(&mut self, in_flight: usize) {
+        self.reduce(in_flight);
+        // the loss window
+        self.cwnd = self.mss;
+    }
+}
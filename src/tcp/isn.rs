@@ -0,0 +1,46 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Instant;
+
+use crate::tcp::Quad;
+
+/// Picks the initial sequence number for a new connection. The manager
+/// uses `Rfc6528Isn` unless another generator is set, e.g. a fixed one
+/// to get reproducible sequence numbers in tests.
+pub trait IsnGenerator {
+    fn generate(&mut self, quad: &Quad) -> u32;
+}
+
+/// ISN = M + F(quad, secret) as described in RFC 6528. M is a clock that
+/// ticks every 4 microseconds so a reused quad moves forward in sequence
+/// space, F is a keyed hash so ISNs can not be predicted off path.
+pub struct Rfc6528Isn {
+    /// SipHash keyed with random keys chosen when the stack starts
+    secret: RandomState,
+    epoch: Instant,
+}
+
+impl Rfc6528Isn {
+    pub fn new() -> Self {
+        Self {
+            secret: RandomState::new(),
+            epoch: Instant::now(),
+        }
+    }
+}
+
+impl Default for Rfc6528Isn {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IsnGenerator for Rfc6528Isn {
+    fn generate(&mut self, quad: &Quad) -> u32 {
+        let ticks = (self.epoch.elapsed().as_micros() / 4) as u32;
+
+        let hash = self.secret.hash_one(quad) as u32;
+
+        ticks.wrapping_add(hash)
+    }
+}
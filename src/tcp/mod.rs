@@ -0,0 +1,2963 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::{Ipv4Addr, Shutdown, SocketAddrV4};
+use std::time::{Duration, Instant};
+
+use crate::parse::ipv4::{IpPayload, Ipv4Packet};
+use crate::parse::ipv4_header::Ipv4Header;
+use crate::parse::ipv4_header_slice::Ipv4HeaderSlice;
+use crate::parse::protocol::Protocol;
+use crate::parse::tcp::{MIN_TCP_HEADER_LENGTH, PsuedoHeader, TcpHeader};
+use crate::parse::tcp_option::{SackBlocks, TcpOption, options_length};
+use crate::parse::tcp_slice::TcpHeaderSlice;
+pub use congestion::{
+    AckInfo, Bbr, BbrMode, CongestionAlgorithm, CongestionControl, Cubic, NewReno, RateSample,
+};
+pub use isn::{IsnGenerator, Rfc6528Isn};
+use recv_buffer::RecvBuffer;
+use retransmit::{DUP_THRESH, RetransmitQueue, RttEstimator};
+use send_buffer::SendBuffer;
+use seq::SeqNum;
+use syn_cookie::SynCookies;
+use timestamps::Timestamps;
+
+mod congestion;
+
+mod isn;
+
+mod rack;
+
+mod recv_buffer;
+
+mod retransmit;
+
+mod send_buffer;
+
+mod seq;
+
+mod syn_cookie;
+
+mod timestamps;
+
+const DEFAULT_TTL: u8 = 64;
+const RECV_BUFFER_SIZE: usize = 256 * 1024;
+const SEND_BUFFER_SIZE: usize = 256 * 1024;
+/// MSS to assume when the peer does not tell us one
+const DEFAULT_MSS: usize = 536;
+/// MSS we advertise, tun0 has a 1500 byte MTU minus 20 bytes each for
+/// the IP and TCP headers
+const DEFAULT_LOCAL_MSS: u16 = 1460;
+const DEFAULT_MAX_RETRIES: u32 = 15;
+/// the address run.sh gives our end of tun0
+const DEFAULT_LOCAL_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+/// challenge ACKs a connection may send per second
+const DEFAULT_CHALLENGE_ACK_LIMIT: u32 = 10;
+/// how long the ACK of in order data may be held back
+const DEFAULT_ACK_DELAY: Duration = Duration::from_millis(40);
+/// longest the persist timer backs off to between zero window probes
+const MAX_PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+/// longest a corked connection holds back a partial segment, as in Linux
+const MAX_CORK_DELAY: Duration = Duration::from_millis(200);
+/// how long a passively opened connection may take to complete the
+/// handshake, the connection establishment timer from 4.4BSD
+const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(75);
+/// half-open connections before SYNs are answered with SYN cookies
+const DEFAULT_MAX_HALF_OPEN: usize = 256;
+/// maximum segment lifetime, TIME-WAIT lasts twice this
+const DEFAULT_MSL: Duration = Duration::from_secs(30);
+/// keepalive defaults from RFC 1122 section 4.2.3.6 and Linux
+const DEFAULT_KEEPALIVE_IDLE: Duration = Duration::from_secs(2 * 60 * 60);
+const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(75);
+const DEFAULT_KEEPALIVE_PROBES: u32 = 9;
+/// full-sized segments received before an ACK goes out without waiting
+/// for the delayed ACK timer (RFC 9293 section 3.8.6.3)
+const ACK_EVERY_SEGMENTS: u32 = 2;
+/// IANA dynamic port range used for outgoing connections
+const EPHEMERAL_PORT_START: u16 = 49152;
+const EPHEMERAL_PORT_END: u16 = 65535;
+/// largest shift count allowed by RFC 7323
+const MAX_WINDOW_SHIFT: u8 = 14;
+/// probe timeout used before there is a round trip estimate (RFC 8985)
+const INITIAL_PTO: Duration = Duration::from_secs(1);
+/// longest the peer may delay the ACK of a lone segment, added to the probe
+/// timeout when only one segment is in flight
+const WORST_CASE_ACK_DELAY: Duration = Duration::from_millis(200);
+
+/// smallest shift count that lets a window of `size` bytes be advertised
+fn window_shift(size: usize) -> u8 {
+    let mut shift = 0;
+    while size >> shift > u16::MAX as usize && shift < MAX_WINDOW_SHIFT {
+        shift += 1;
+    }
+    shift
+}
+
+/// TSval and TSecr of the segment's Timestamps option
+fn segment_timestamps(tcp: &TcpHeaderSlice<'_>) -> Option<(u32, u32)> {
+    tcp.options_iter().find_map(|option| match option {
+        TcpOption::Timestamps { value, echo_reply } => Some((value, echo_reply)),
+        _ => None,
+    })
+}
+
+/// sequence space taken up by a segment, SYN and FIN count as one each
+fn segment_len(tcp: &TcpHeaderSlice<'_>) -> u32 {
+    tcp.data().len() as u32 + tcp.syn() as u32 + tcp.fin() as u32
+}
+
+#[derive(Default, Debug)]
+struct SendSeq {
+    /// send unacknowledged
+    una: SeqNum,
+    /// send next
+    nxt: SeqNum,
+    /// highest sequence number sent so far, `nxt` moves back
+    /// to `una` when we retransmit
+    max: SeqNum,
+    /// send window
+    wnd: u32,
+    /// send urgent pointer
+    // TODO: sending urgent data is not supported yet
+    #[allow(dead_code)]
+    up: SeqNum,
+    /// segment sequence number used for last window update
+    wl1: SeqNum,
+    /// segment acknowledgment number used for last window update
+    wl2: SeqNum,
+    /// largest window the peer has advertised
+    max_wnd: u32,
+    /// initial send sequence number
+    iss: SeqNum,
+    /// shift count applied to windows the peer advertises
+    wnd_shift: u8,
+}
+
+#[derive(Default, Debug)]
+struct RecvSeq {
+    /// receive next
+    nxt: SeqNum,
+    /// receive window
+    wnd: u32,
+    /// receive urgent pointer
+    up: SeqNum,
+    /// initial receive sequence number
+    irs: SeqNum,
+    /// shift count applied to windows we advertise
+    wnd_shift: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynRecieved,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+impl TcpState {
+    /// true once the three way handshake has completed
+    fn is_synchronized(self) -> bool {
+        !matches!(
+            self,
+            TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynRecieved
+        )
+    }
+
+    /// true once the peer has sent its FIN
+    fn fin_received(self) -> bool {
+        matches!(
+            self,
+            TcpState::CloseWait | TcpState::Closing | TcpState::LastAck | TcpState::TimeWait
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpError {
+    ConnectionReset,
+    /// there is no connection for the quad
+    NotConnected,
+    /// the operation can not make progress until more segments arrive
+    WouldBlock,
+    /// the connection has been closed for sending
+    Closing,
+    /// the peer stopped acknowledging our segments
+    TimedOut,
+    /// the peer answered our SYN with a reset
+    ConnectionRefused,
+    /// every ephemeral port to the remote address is in use
+    AddrNotAvailable,
+    /// something is already listening on the address
+    AddrInUse,
+}
+
+impl fmt::Display for TcpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            TcpError::ConnectionReset => "connection reset by peer",
+            TcpError::NotConnected => "not connected",
+            TcpError::WouldBlock => "operation would block",
+            TcpError::Closing => "connection is closing",
+            TcpError::TimedOut => "connection timed out",
+            TcpError::ConnectionRefused => "connection refused",
+            TcpError::AddrNotAvailable => "address not available",
+            TcpError::AddrInUse => "address already in use",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for TcpError {}
+
+/// When an idle connection is checked for a peer that went away
+/// (RFC 1122 section 4.2.3.6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    /// how long the connection has to be quiet before the first probe
+    pub idle: Duration,
+    /// time between unanswered probes
+    pub interval: Duration,
+    /// unanswered probes before the connection is aborted
+    pub probes: u32,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Self {
+            idle: DEFAULT_KEEPALIVE_IDLE,
+            interval: DEFAULT_KEEPALIVE_INTERVAL,
+            probes: DEFAULT_KEEPALIVE_PROBES,
+        }
+    }
+}
+
+/// Settings applied to every connection the manager creates
+#[derive(Debug, Clone)]
+pub struct TcpConfig {
+    /// our address, used as the source of outgoing connections
+    pub local_ip: Ipv4Addr,
+    /// how many times the retransmission timer may expire for the same
+    /// data before the connection is aborted
+    pub max_retries: u32,
+    /// how many RFC 5961 challenge ACKs a connection may send per second
+    pub challenge_ack_limit: u32,
+    /// largest segment we are willing to receive, sent on our SYNs
+    pub mss: u16,
+    /// how long a passively opened connection may stay in SYN-RECEIVED,
+    /// the SYN-ACK is retransmitted in the meantime
+    pub handshake_timeout: Duration,
+    /// passively opened connections that may wait for the end of the
+    /// handshake, past this SYNs are answered with SYN cookies so a SYN
+    /// flood can not use up memory
+    pub max_half_open: usize,
+    /// maximum segment lifetime, a connection we closed first stays in
+    /// TIME-WAIT for twice this long so old segments die out before the
+    /// quad can be reused
+    pub msl: Duration,
+    /// how long the ACK of in order data may wait for more data to
+    /// acknowledge along with it, RFC 9293 requires less than 500ms
+    pub ack_delay: Duration,
+    /// keepalive for new connections, off unless set as RFC 1122 requires,
+    /// see `TcpConnManager::set_keepalive` to change it for one
+    pub keepalive: Option<Keepalive>,
+    /// algorithm new connections start with, see
+    /// `TcpConnManager::set_congestion_control` to change it for one
+    pub congestion_control: CongestionAlgorithm,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            local_ip: DEFAULT_LOCAL_IP,
+            max_retries: DEFAULT_MAX_RETRIES,
+            challenge_ack_limit: DEFAULT_CHALLENGE_ACK_LIMIT,
+            mss: DEFAULT_LOCAL_MSS,
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            max_half_open: DEFAULT_MAX_HALF_OPEN,
+            msl: DEFAULT_MSL,
+            ack_delay: DEFAULT_ACK_DELAY,
+            keepalive: None,
+            congestion_control: CongestionAlgorithm::default(),
+        }
+    }
+}
+
+/// Things the application needs to know about, returned by `TcpConnManager::poll_event`
+#[derive(Debug)]
+pub enum TcpEvent {
+    /// the three way handshake of a connection we opened completed
+    Established(Quad),
+    /// a connection to the listener at the address completed the handshake
+    /// and is waiting to be taken with `TcpConnManager::accept`
+    Acceptable(SocketAddrV4),
+    /// new data can be read with `TcpConnManager::read`
+    Readable(Quad),
+    /// the peer sent a FIN, it will not send any more data
+    PeerClosed(Quad),
+    /// the connection closed gracefully and has been removed, data that
+    /// arrived before the close is read to the end of the stream first
+    Closed(Quad),
+    /// the connection was torn down because of an error and has been removed
+    Aborted(Quad, TcpError),
+}
+
+/// A TCP segment waiting to be sent. Unlike `TcpHeader` it owns its data
+/// so it can be queued after the packet that caused it is gone.
+pub struct Segment {
+    src: SocketAddrV4,
+    dst: SocketAddrV4,
+    seq_number: SeqNum,
+    ack_number: SeqNum,
+    ack: bool,
+    rst: bool,
+    syn: bool,
+    fin: bool,
+    window: u16,
+    options: Vec<TcpOption<'static>>,
+    data: Vec<u8>,
+}
+
+impl Segment {
+    /// Builds the reset sent in reply to a segment that does not belong to
+    /// any connection. A reset is never answered with another reset.
+    fn reset_for(ip: &Ipv4HeaderSlice<'_>, tcp: &TcpHeaderSlice<'_>) -> Option<Self> {
+        if tcp.rst() {
+            return None;
+        }
+
+        // if the segment had an ACK the reset takes its sequence number from
+        // it, otherwise we acknowledge the segment so the peer accepts the reset
+        let (seq_number, ack_number, ack) = if tcp.ack() {
+            (SeqNum::from(tcp.ack_number()), SeqNum::default(), false)
+        } else {
+            let ack_number = SeqNum::from(tcp.seq_number()) + segment_len(tcp);
+            (SeqNum::default(), ack_number, true)
+        };
+
+        Some(Self {
+            src: SocketAddrV4::new(ip.dst_ip(), tcp.dst_port()),
+            dst: SocketAddrV4::new(ip.src_ip(), tcp.src_port()),
+            seq_number,
+            ack_number,
+            ack,
+            rst: true,
+            syn: false,
+            fin: false,
+            window: 0,
+            options: Vec::new(),
+            data: Vec::new(),
+        })
+    }
+
+    /// Builds the SYN-ACK that answers a SYN with the cookie `iss`. Nothing
+    /// is stored for the connection so the MSS is the only option offered.
+    fn syn_cookie_for(
+        ip: &Ipv4HeaderSlice<'_>,
+        tcp: &TcpHeaderSlice<'_>,
+        iss: SeqNum,
+        mss: u16,
+    ) -> Self {
+        Self {
+            src: SocketAddrV4::new(ip.dst_ip(), tcp.dst_port()),
+            dst: SocketAddrV4::new(ip.src_ip(), tcp.src_port()),
+            seq_number: iss,
+            ack_number: SeqNum::from(tcp.seq_number()) + 1,
+            ack: true,
+            rst: false,
+            syn: true,
+            fin: false,
+            window: RECV_BUFFER_SIZE.min(u16::MAX as usize) as u16,
+            options: vec![TcpOption::MaxSegmentSize(mss)],
+            data: Vec::new(),
+        }
+    }
+
+    pub fn tcp_header(&self) -> TcpHeader<'_> {
+        let psuedo_header = PsuedoHeader {
+            src_addr: *self.src.ip(),
+            dst_addr: *self.dst.ip(),
+            protocol: Protocol::Tcp,
+            tcp_length: (MIN_TCP_HEADER_LENGTH + options_length(&self.options) + self.data.len())
+                as u16,
+        };
+
+        TcpHeader {
+            src_port: self.src.port(),
+            dst_port: self.dst.port(),
+            seq_number: self.seq_number.into(),
+            ack_number: self.ack_number.into(),
+            cwr: false,
+            ece: false,
+            urg: false,
+            ack: self.ack,
+            psh: !self.data.is_empty(),
+            rst: self.rst,
+            syn: self.syn,
+            fin: self.fin,
+            window: self.window,
+            psuedo_header,
+            urgent_pointer: 0,
+            options: &self.options,
+            data: &self.data,
+        }
+    }
+
+    pub fn ip_header(&self) -> Ipv4Header {
+        Ipv4Header {
+            tos: 0,
+            identification: 0,
+            dont_fragment: true,
+            more_fragments: false,
+            fragment_offset: 0,
+            ttl: DEFAULT_TTL,
+            protocol: Protocol::Tcp,
+            src_ip: *self.src.ip(),
+            dst_ip: *self.dst.ip(),
+        }
+    }
+
+    pub fn to_packet(&self) -> Ipv4Packet<'_> {
+        Ipv4Packet::new(self.ip_header(), IpPayload::Tcp(self.tcp_header()))
+    }
+}
+
+impl fmt::Debug for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Segment")
+            .field("source", &self.src)
+            .field("destination", &self.dst)
+            .field("sequence number", &self.seq_number)
+            .field("acknowledgment number", &self.ack_number)
+            .field("flag (ack)", &self.ack)
+            .field("flag (rst)", &self.rst)
+            .field("flag (syn)", &self.syn)
+            .field("flag (fin)", &self.fin)
+            .field("window", &self.window)
+            .field("options", &self.options)
+            .field("data length", &self.data.len())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct TcpConn {
+    quad: Quad,
+    config: TcpConfig,
+    state: TcpState,
+    /// true if we sent the first SYN
+    active_open: bool,
+    rcv: RecvSeq,
+    snd: SendSeq,
+    recv_buffer: RecvBuffer,
+    send_buffer: SendBuffer,
+    /// largest segment the peer is willing to receive
+    mss: usize,
+    /// both ends sent the Window Scale option so windows are shifted
+    window_scaling: bool,
+    /// both ends sent SACK-Permitted so SACK blocks can be exchanged
+    sack_permitted: bool,
+    /// both ends sent the Timestamps option so every segment carries one
+    timestamps_enabled: bool,
+    timestamps: Timestamps,
+    /// acknowledgment number of the last segment we sent, Last.ACK.sent
+    /// in RFC 7323
+    last_ack_sent: SeqNum,
+    /// the application closed, a FIN goes out after the buffered data
+    fin_queued: bool,
+    /// sequence number of our FIN once it has been sent
+    fin_seq: Option<SeqNum>,
+    /// segments in flight, used to time round trips
+    retransmit_queue: RetransmitQueue,
+    rtt: RttEstimator,
+    /// when the retransmission timer expires, `None` while nothing is in flight
+    rto_deadline: Option<Instant>,
+    /// times the retransmission timer expired without progress
+    retries: u32,
+    /// limits what is in flight on top of the peer's window
+    congestion: Box<dyn CongestionControl>,
+    /// duplicate ACKs received in a row
+    dup_acks: u32,
+    /// in fast recovery until everything up to `recover` is acknowledged
+    in_recovery: bool,
+    /// highest sequence number sent when recovery last started (RFC 6582)
+    recover: SeqNum,
+    /// earliest time the next segment may go out when the congestion
+    /// control paces
+    next_send: Option<Instant>,
+    /// set when sending stopped to wait for `next_send`
+    pacing_timer: Option<Instant>,
+    /// when RACK has to look at segments that were not overdue yet
+    rack_timer: Option<Instant>,
+    /// when to send a tail loss probe
+    tlp_timer: Option<Instant>,
+    /// `SND.MAX` when the outstanding tail loss probe was sent
+    tlp_end_seq: Option<SeqNum>,
+    /// the outstanding probe resent old data rather than new data
+    tlp_retransmitted: bool,
+    /// when the delayed ACK has to go out, `None` if no ACK is owed
+    ack_timer: Option<Instant>,
+    /// our SYN has been acknowledged. `SND.UNA == ISS` can not tell since
+    /// it holds again once the sequence space has wrapped around.
+    syn_acked: bool,
+    /// full-sized segments received since we last sent an ACK
+    unacked_segments: u32,
+    /// acknowledge every data segment right away instead of delaying
+    quick_ack: bool,
+    /// send small segments even while data is unacknowledged, turns
+    /// Nagle's algorithm off
+    nodelay: bool,
+    /// hold back partial segments until the application uncorks
+    corked: bool,
+    /// when a partial segment held back by the cork goes out anyway
+    cork_deadline: Option<Instant>,
+    /// the application will not read anymore, incoming data is
+    /// acknowledged and thrown away
+    read_shutdown: bool,
+    /// a read returned the end of the stream, until then a closed
+    /// connection with unread data is kept around
+    eof_read: bool,
+    /// when to probe the peer's zero window, `None` while the window is
+    /// open or data is in flight
+    persist_timer: Option<Instant>,
+    /// probes sent since the window closed, doubles the interval each time
+    persist_backoff: u32,
+    /// probes the peer has not answered, the connection is aborted after
+    /// `max_retries` of them
+    unanswered_probes: u32,
+    keepalive: Option<Keepalive>,
+    /// when to send the next keepalive probe
+    keepalive_timer: Option<Instant>,
+    /// keepalive probes sent since the peer was last heard from
+    keepalive_probes: u32,
+    /// when TIME-WAIT ends and the connection is removed
+    time_wait_deadline: Option<Instant>,
+    /// when a passively opened connection is given up on if the handshake
+    /// has not completed
+    handshake_deadline: Option<Instant>,
+    /// start of the second `challenge_acks` is counted in
+    challenge_ack_start: Instant,
+    challenge_acks: u32,
+    /// set when new in order data arrived, cleared once the application is told
+    readable: bool,
+    /// why the connection was aborted, reported once it reaches `Closed`
+    error: Option<TcpError>,
+    /// segments waiting to be handed to the interface
+    outgoing: VecDeque<Segment>,
+}
+
+impl TcpConn {
+    fn new(quad: Quad, config: TcpConfig, iss: SeqNum) -> Self {
+        let congestion = config.congestion_control.build();
+        let keepalive = config.keepalive;
+
+        Self {
+            quad,
+            config,
+            state: TcpState::Listen,
+            active_open: false,
+            rcv: RecvSeq {
+                wnd_shift: window_shift(RECV_BUFFER_SIZE),
+                ..RecvSeq::default()
+            },
+            snd: SendSeq {
+                iss,
+                ..SendSeq::default()
+            },
+            recv_buffer: RecvBuffer::new(RECV_BUFFER_SIZE),
+            send_buffer: SendBuffer::new(SEND_BUFFER_SIZE),
+            mss: DEFAULT_MSS,
+            window_scaling: false,
+            sack_permitted: false,
+            timestamps_enabled: false,
+            // offset the clock by the ISS so it is as hard to guess
+            timestamps: Timestamps::new(iss.into()),
+            last_ack_sent: SeqNum::default(),
+            fin_queued: false,
+            fin_seq: None,
+            retransmit_queue: RetransmitQueue::new(),
+            rtt: RttEstimator::new(),
+            rto_deadline: None,
+            retries: 0,
+            congestion,
+            dup_acks: 0,
+            in_recovery: false,
+            recover: iss,
+            next_send: None,
+            pacing_timer: None,
+            rack_timer: None,
+            tlp_timer: None,
+            tlp_end_seq: None,
+            tlp_retransmitted: false,
+            ack_timer: None,
+            syn_acked: false,
+            unacked_segments: 0,
+            quick_ack: false,
+            nodelay: false,
+            corked: false,
+            cork_deadline: None,
+            read_shutdown: false,
+            eof_read: false,
+            persist_timer: None,
+            persist_backoff: 0,
+            unanswered_probes: 0,
+            keepalive,
+            keepalive_timer: None,
+            keepalive_probes: 0,
+            time_wait_deadline: None,
+            handshake_deadline: None,
+            challenge_ack_start: Instant::now(),
+            challenge_acks: 0,
+            readable: false,
+            error: None,
+            outgoing: VecDeque::new(),
+        }
+    }
+
+    /// Rebuilds a passively opened connection from a valid SYN cookie, as if
+    /// it had sent the SYN-ACK for `irs` with `iss` and `mss` itself
+    fn from_cookie(quad: Quad, config: TcpConfig, iss: SeqNum, irs: SeqNum, mss: u16) -> Self {
+        let mut connection = Self::new(quad, config, iss);
+
+        connection.state = TcpState::SynRecieved;
+        connection.snd.una = iss;
+        connection.snd.nxt = iss + 1;
+        connection.snd.max = iss + 1;
+        connection.rcv.irs = irs;
+        connection.rcv.nxt = irs + 1;
+        connection.rcv.wnd = connection.recv_buffer.window() as u32;
+        connection.rcv.wnd_shift = 0;
+
+        // we never send more than we would accept ourselves
+        connection.mss = usize::from(mss).clamp(1, usize::from(connection.config.mss));
+        connection.congestion.set_mss(connection.mss);
+        connection
+    }
+
+    /// Builds a bare ACK carrying `seq`. Other control bits can be set
+    /// with struct update syntax.
+    fn segment(&self, seq_number: SeqNum) -> Segment {
+        Segment {
+            src: self.quad.local(),
+            dst: self.quad.remote(),
+            seq_number,
+            ack_number: self.rcv.nxt,
+            ack: true,
+            rst: false,
+            syn: false,
+            fin: false,
+            window: (self.rcv.wnd >> self.rcv.wnd_shift).min(u16::MAX as u32) as u16,
+            options: self.ack_options(),
+            data: Vec::new(),
+        }
+    }
+
+    /// Our timestamp and SACK blocks describing the out of order data we
+    /// hold (RFC 2018). With timestamps only 3 blocks fit.
+    fn ack_options(&self) -> Vec<TcpOption<'static>> {
+        let mut options = Vec::new();
+
+        if self.timestamps_enabled {
+            options.push(self.timestamps_option());
+        }
+
+        if !self.sack_permitted {
+            return options;
+        }
+
+        let max_blocks = if self.timestamps_enabled { 3 } else { 4 };
+        let mut blocks = SackBlocks::new();
+        for (start, end) in self.recv_buffer.sack_blocks().into_iter().take(max_blocks) {
+            let left = self.rcv.nxt + start as u32;
+            let right = self.rcv.nxt + end as u32;
+            blocks.push(left.into(), right.into());
+        }
+
+        if !blocks.is_empty() {
+            options.push(TcpOption::Sack(blocks));
+        }
+        options
+    }
+
+    fn timestamps_option(&self) -> TcpOption<'static> {
+        TcpOption::Timestamps {
+            value: self.timestamps.clock(Instant::now()),
+            echo_reply: self.timestamps.recent(),
+        }
+    }
+
+    /// Builds our SYN, or our SYN-ACK if `ack` is set, with the options we
+    /// offer. The window on a SYN is never scaled so it is capped at what
+    /// fits in the header.
+    fn syn_segment(&self, ack: bool) -> Segment {
+        let mut options = vec![TcpOption::MaxSegmentSize(self.config.mss)];
+
+        // a SYN-ACK may only carry Window Scale if the SYN did
+        if !ack || self.window_scaling {
+            options.push(TcpOption::WindowScale(self.rcv.wnd_shift));
+        }
+
+        if !ack || self.sack_permitted {
+            options.push(TcpOption::SackPermitted);
+        }
+
+        // TSecr on our SYN is zero since there is nothing to echo yet
+        if !ack || self.timestamps_enabled {
+            options.push(self.timestamps_option());
+        }
+
+        Segment {
+            ack,
+            ack_number: if ack { self.rcv.nxt } else { SeqNum::default() },
+            syn: true,
+            window: self.rcv.wnd.min(u16::MAX as u32) as u16,
+            options,
+            ..self.segment(self.snd.iss)
+        }
+    }
+
+    /// Picks up the options the peer sent on its SYN. Without an MSS
+    /// option we have to assume the RFC 9293 default of 536.
+    fn on_syn_options(&mut self, tcp: &TcpHeaderSlice<'_>) {
+        self.mss = DEFAULT_MSS;
+        let mut peer_shift = None;
+        self.sack_permitted = false;
+        self.timestamps_enabled = false;
+
+        for option in tcp.options_iter() {
+            match option {
+                TcpOption::MaxSegmentSize(mss) => self.mss = usize::from(mss),
+                TcpOption::WindowScale(shift) => peer_shift = Some(shift),
+                TcpOption::SackPermitted => self.sack_permitted = true,
+                TcpOption::Timestamps { value, .. } => {
+                    self.timestamps_enabled = true;
+                    self.timestamps.set_recent(value, Instant::now());
+                }
+                _ => {}
+            }
+        }
+
+        // RFC 7323, scaling is only on if both SYNs carried the option,
+        // a shift over 14 is treated as 14
+        self.window_scaling = peer_shift.is_some();
+        self.snd.wnd_shift = peer_shift.unwrap_or(0).min(MAX_WINDOW_SHIFT);
+        if !self.window_scaling {
+            self.rcv.wnd_shift = 0;
+        }
+
+        // we never send more than we would accept ourselves
+        self.mss = self.mss.clamp(1, usize::from(self.config.mss));
+        self.congestion.set_mss(self.mss);
+    }
+
+    /// Queues a segment for the interface. Anything that takes up sequence
+    /// space is remembered until it is acknowledged
+    fn transmit(&mut self, segment: Segment) {
+        let len = segment.data.len() as u32 + segment.syn as u32 + segment.fin as u32;
+
+        if len > 0 {
+            let now = Instant::now();
+            let end = segment.seq_number + len;
+
+            let retransmitted = segment.seq_number < self.snd.max;
+            self.retransmit_queue
+                .push(segment.seq_number, len, now, retransmitted);
+
+            if self.snd.max < end {
+                self.snd.max = end;
+            }
+
+            if self.rto_deadline.is_none() {
+                self.rto_deadline = Some(now + self.rtt.rto());
+            }
+        }
+
+        self.send(segment);
+    }
+
+    /// Hands a segment to the interface without tracking its sequence space
+    fn send(&mut self, segment: Segment) {
+        if segment.ack {
+            // every segment carries our ACK, so nothing is owed anymore
+            self.last_ack_sent = segment.ack_number;
+            self.ack_timer = None;
+            self.unacked_segments = 0;
+        }
+
+        self.outgoing.push_back(segment);
+    }
+
+    fn send_ack(&mut self) {
+        let segment = self.segment(self.snd.nxt);
+        self.transmit(segment);
+    }
+
+    fn send_syn(&mut self) {
+        let segment = self.syn_segment(false);
+        self.snd.nxt = self.snd.iss + 1;
+        self.transmit(segment);
+    }
+
+    fn send_syn_ack(&mut self) {
+        let segment = self.syn_segment(true);
+        self.snd.nxt = self.snd.iss + 1;
+        self.transmit(segment);
+    }
+
+    /// Acknowledges a data segment that was just received. In order data
+    /// may wait for the delayed ACK timer, though every second full-sized
+    /// segment is acknowledged at once. Out of order data, or data that
+    /// fills a hole, is acknowledged immediately so the peer's loss
+    /// recovery hears about it (RFC 5681 section 4.2).
+    fn ack_data(&mut self, tcp: &TcpHeaderSlice<'_>, in_order: bool) {
+        // the peer takes its options out of the MSS like we do
+        if tcp.data().len() + tcp.options().len() >= self.mss {
+            self.unacked_segments += 1;
+        }
+
+        if !in_order || self.quick_ack || self.unacked_segments >= ACK_EVERY_SEGMENTS {
+            self.send_ack();
+            return;
+        }
+
+        if self.ack_timer.is_none() {
+            self.ack_timer = Some(Instant::now() + self.config.ack_delay);
+        }
+    }
+
+    /// Turns quick-ACK mode on or off, an ACK that is being delayed goes
+    /// out right away once it is on
+    fn set_quick_ack(&mut self, quick_ack: bool) {
+        self.quick_ack = quick_ack;
+        if quick_ack && self.ack_timer.is_some() {
+            self.send_ack();
+        }
+    }
+
+    /// Turns Nagle's algorithm off, or back on
+    fn set_nodelay(&mut self, nodelay: bool) {
+        self.nodelay = nodelay;
+        self.send_pending();
+    }
+
+    /// Like `TCP_CORK`, partial segments are held back until `uncork` is
+    /// called so the application can build full segments out of several
+    /// writes. A partial segment waits at most `MAX_CORK_DELAY`.
+    fn cork(&mut self) {
+        self.corked = true;
+    }
+
+    /// Sends whatever `cork` held back
+    fn uncork(&mut self) {
+        self.corked = false;
+        self.cork_deadline = None;
+        self.send_pending();
+    }
+
+    /// Sends an ACK in reply to a RST, SYN or ACK that may have been forged
+    /// (RFC 5961). The real peer will answer it, a blind attacker never
+    /// sees it. Rate limited so we can not be used to flood the peer.
+    fn send_challenge_ack(&mut self) {
+        let now = Instant::now();
+        if now - self.challenge_ack_start >= Duration::from_secs(1) {
+            self.challenge_ack_start = now;
+            self.challenge_acks = 0;
+        }
+
+        if self.challenge_acks >= self.config.challenge_ack_limit {
+            return;
+        }
+
+        self.challenge_acks += 1;
+        self.send_ack();
+    }
+
+    /// Cuts the unsent part of the send buffer into segments that fit the
+    /// peer's MSS, the usable window and the congestion window, then sends
+    /// our FIN once everything in front of it has gone out. A partial
+    /// segment waits while data is unacknowledged (Nagle's algorithm, RFC
+    /// 1122 section 4.2.3.4) or while the connection is corked.
+    fn send_pending(&mut self) {
+        let fin_sent = self.fin_seq.is_some_and(|fin| fin < self.snd.nxt);
+        if !self.state.is_synchronized() || fin_sent {
+            return;
+        }
+
+        let mut sent = false;
+
+        loop {
+            let unsent = self.send_buffer.len() - self.bytes_in_flight();
+            if unsent == 0 {
+                self.cork_deadline = None;
+                if self.pipe() < self.congestion.window() {
+                    self.retransmit_queue.set_app_limited(self.pipe() as u32);
+                }
+                break;
+            }
+
+            let now = Instant::now();
+            if self.next_send.is_some_and(|next_send| next_send > now) {
+                self.pacing_timer = self.next_send;
+                break;
+            }
+
+            let window_end = self.snd.una + self.snd.wnd;
+            let congestion_room = self.congestion.window().saturating_sub(self.pipe());
+            if self.snd.nxt >= window_end || congestion_room == 0 {
+                break;
+            }
+
+            let usable = ((window_end - self.snd.nxt) as usize).min(congestion_room);
+            // the last bit of data before our FIN is never held back
+            let partial = unsent.min(usable) < self.max_segment_data() && !self.fin_queued;
+            let unacked = self.bytes_in_flight() > 0;
+            if partial && self.corked {
+                let deadline = *self.cork_deadline.get_or_insert(now + MAX_CORK_DELAY);
+                if deadline > now {
+                    break;
+                }
+            } else if partial && !self.nodelay && unacked {
+                break;
+            }
+
+            let len = self.send_segment(usable);
+            sent = true;
+            if partial {
+                self.cork_deadline = None;
+            }
+
+            if let Some(rate) = self.congestion.pacing_rate() {
+                let gap = Duration::from_secs_f64(len as f64 / rate);
+                self.next_send = Some(self.next_send.unwrap_or(now).max(now) + gap);
+            }
+        }
+
+        if sent && self.tlp_timer.is_none() {
+            self.schedule_loss_probe();
+        }
+
+        // with a zero window and nothing in flight no ACK is coming that
+        // could open it, so it has to be probed (RFC 9293 section 3.8.6.1)
+        let unsent = self.send_buffer.len() - self.bytes_in_flight();
+        if self.snd.wnd == 0
+            && unsent > 0
+            && self.retransmit_queue.is_empty()
+            && self.persist_timer.is_none()
+        {
+            self.persist_timer = Some(Instant::now() + self.persist_interval());
+        }
+
+        if self.fin_queued && self.bytes_in_flight() == self.send_buffer.len() {
+            let segment = Segment {
+                fin: true,
+                ..self.segment(self.snd.nxt)
+            };
+            self.fin_seq = Some(self.snd.nxt);
+            self.snd.nxt += 1;
+            self.transmit(segment);
+        }
+    }
+
+    /// Sends the next segment of unsent data, at most `usable` bytes of it,
+    /// and returns how much was sent
+    fn send_segment(&mut self, usable: usize) -> usize {
+        let in_flight = self.bytes_in_flight();
+        let unsent = self.send_buffer.len() - in_flight;
+
+        let mut segment = self.segment(self.snd.nxt);
+        let len = unsent.min(usable).min(self.max_segment_data());
+
+        segment.data = self.send_buffer.read(in_flight, len);
+        self.snd.nxt += len as u32;
+        self.transmit(segment);
+        len
+    }
+
+    /// Sends a zero window probe, one byte of new data past the window. The
+    /// peer has to answer it with its current window, and if the byte fits
+    /// after all it is acknowledged like any other data. Probing goes on
+    /// with exponential backoff for as long as the peer answers.
+    fn send_window_probe(&mut self, now: Instant) {
+        let in_flight = self.bytes_in_flight();
+        if self.snd.wnd > 0
+            || self.send_buffer.len() == in_flight
+            || !self.retransmit_queue.is_empty()
+        {
+            return;
+        }
+
+        self.unanswered_probes += 1;
+        if self.unanswered_probes > self.config.max_retries {
+            self.abort(TcpError::TimedOut);
+            return;
+        }
+
+        let segment = Segment {
+            data: self.send_buffer.read(in_flight, 1),
+            ..self.segment(self.snd.nxt)
+        };
+        // the byte is not in flight, but the ACK for it has to be acceptable
+        let end = self.snd.nxt + 1;
+        if self.snd.max < end {
+            self.snd.max = end;
+        }
+        self.send(segment);
+
+        self.persist_backoff += 1;
+        self.persist_timer = Some(now + self.persist_interval());
+    }
+
+    /// time until the next zero window probe, the retransmission timeout
+    /// doubled for every probe already sent
+    fn persist_interval(&self) -> Duration {
+        let backoff = 1 << self.persist_backoff.min(16);
+        (self.rtt.rto() * backoff).min(MAX_PERSIST_INTERVAL)
+    }
+
+    /// Sends a keepalive probe once the connection has been idle long
+    /// enough. The probe carries `SND.NXT - 1`, which the peer has already
+    /// acknowledged, so it has to answer with an ACK. The connection is
+    /// aborted once `Keepalive::probes` probes go unanswered.
+    fn send_keepalive(&mut self, now: Instant) {
+        let Some(keepalive) = self.keepalive else {
+            return;
+        };
+
+        // the retransmission and persist timers already notice a dead peer
+        // while there is data to send
+        let unsent = self.send_buffer.len() - self.bytes_in_flight();
+        if !self.retransmit_queue.is_empty() || unsent > 0 {
+            self.keepalive_timer = Some(now + keepalive.idle);
+            return;
+        }
+
+        if self.keepalive_probes >= keepalive.probes {
+            self.abort(TcpError::TimedOut);
+            return;
+        }
+
+        let segment = self.segment(self.snd.nxt - 1);
+        self.send(segment);
+
+        self.keepalive_probes += 1;
+        self.keepalive_timer = Some(now + keepalive.interval);
+    }
+
+    /// Restarts the keepalive idle time, the peer was just heard from
+    fn reset_keepalive(&mut self) {
+        self.keepalive_probes = 0;
+        self.keepalive_timer = match self.keepalive {
+            Some(keepalive) if self.state.is_synchronized() && self.state != TcpState::TimeWait => {
+                Some(Instant::now() + keepalive.idle)
+            }
+            _ => None,
+        };
+    }
+
+    fn set_keepalive(&mut self, keepalive: Option<Keepalive>) {
+        self.keepalive = keepalive;
+        self.reset_keepalive();
+    }
+
+    /// most data a segment can carry, the MSS does not include options so
+    /// they come out of the data
+    fn max_segment_data(&self) -> usize {
+        self.mss
+            .saturating_sub(options_length(&self.ack_options()))
+            .max(1)
+    }
+
+    /// data bytes sent but not acknowledged, our SYN also takes up a
+    /// sequence number until it is acknowledged
+    fn bytes_in_flight(&self) -> usize {
+        let syn = !self.syn_acked as usize;
+        (self.snd.nxt - self.snd.una) as usize - syn
+    }
+
+    /// Bytes we believe are still in the network. Without SACK every
+    /// duplicate ACK during recovery stands for a segment that has left.
+    fn pipe(&self) -> usize {
+        let pipe = self.retransmit_queue.pipe() as usize;
+        if self.sack_permitted || !self.in_recovery {
+            return pipe;
+        }
+
+        pipe.saturating_sub(self.dup_acks as usize * self.mss)
+    }
+
+    /// sequence space sent but not acknowledged, FlightSize in RFC 5681
+    fn flight_size(&self) -> usize {
+        (self.snd.max - self.snd.una) as usize
+    }
+
+    /// Advances `SND.UNA` to the segment's acceptable ACK, drops the data
+    /// it covers from the send buffer and restarts the retransmission timer
+    fn acknowledge(&mut self, tcp: &TcpHeaderSlice<'_>) {
+        let now = Instant::now();
+        let ack = SeqNum::from(tcp.ack_number());
+        let syn = !self.syn_acked as usize;
+        let acked = ((ack - self.snd.una) as usize).saturating_sub(syn);
+        self.syn_acked = true;
+        // everything outstanding at the last fast retransmit or timeout
+        // has now arrived
+        let recovered = self.snd.una < self.recover && self.recover <= ack;
+
+        self.send_buffer.acknowledge(acked);
+        self.snd.una = ack;
+
+        // the original transmission arrived after we went back to retransmit
+        if self.snd.nxt < ack {
+            self.snd.nxt = ack;
+        }
+
+        let oldest_sent = self.retransmit_queue.oldest_sent_at();
+        let queue_rtt = self.retransmit_queue.acknowledge(ack, now);
+        // with timestamps every ACK of new data gives a sample, even for
+        // retransmitted segments since the echo says which one arrived
+        let rtt = match (segment_timestamps(tcp), oldest_sent) {
+            (Some((_, echo_reply)), Some(oldest_sent)) if self.timestamps_enabled => self
+                .timestamps
+                .rtt(echo_reply, oldest_sent, now)
+                .or(queue_rtt),
+            _ => queue_rtt,
+        };
+
+        if let Some(rtt) = rtt {
+            self.rtt.sample(rtt);
+        }
+
+        let in_recovery = self.in_recovery;
+        let mut partial_ack = false;
+        if in_recovery {
+            if self.recover <= ack {
+                self.in_recovery = false;
+                self.congestion.on_recovery_end(self.pipe());
+            } else if !self.sack_permitted {
+                // RFC 6582 partial ACK, the next hole was lost too and is
+                // resent right away
+                self.retransmit_queue.mark_first_lost();
+                self.retransmit_next_lost();
+                partial_ack = true;
+            }
+        }
+        if recovered {
+            self.retransmit_queue.on_recovery_end();
+        }
+
+        // Without SACK duplicate ACKs inflate the window by taking a segment
+        // each off `pipe`. A partial ACK deflates that by the data it
+        // acknowledged and adds back a segment if it covered one.
+        self.dup_acks = if partial_ack {
+            let inflation = (self.dup_acks as usize * self.mss).saturating_sub(acked);
+            let refill = if acked >= self.mss { self.mss } else { 0 };
+            ((inflation + refill) / self.mss) as u32
+        } else {
+            0
+        };
+
+        let rate = self.retransmit_queue.take_rate_sample();
+        self.congestion.on_ack(&AckInfo {
+            now,
+            acked,
+            in_flight: self.pipe(),
+            rtt,
+            in_recovery,
+            rate,
+        });
+
+        self.retries = 0;
+        self.rto_deadline = if self.retransmit_queue.is_empty() {
+            None
+        } else {
+            Some(now + self.rtt.rto())
+        };
+    }
+
+    /// Records the SACK blocks on an ACK in the scoreboard (RFC 6675).
+    /// Returns true if the first block is a D-SACK, reporting data that
+    /// arrived twice (RFC 2883).
+    fn on_sack(&mut self, tcp: &TcpHeaderSlice<'_>) -> bool {
+        if !self.sack_permitted {
+            return false;
+        }
+
+        let now = Instant::now();
+        let ack = SeqNum::from(tcp.ack_number());
+        let mut dsack = false;
+
+        for option in tcp.options_iter() {
+            let TcpOption::Sack(blocks) = option else {
+                continue;
+            };
+
+            let blocks: Vec<(SeqNum, SeqNum)> = blocks
+                .iter()
+                .map(|(left, right)| (SeqNum::from(left), SeqNum::from(right)))
+                .collect();
+
+            // a D-SACK is below the ACK or inside the block after it
+            if let Some(&(left, right)) = blocks.first()
+                && (right <= ack
+                    || blocks
+                        .get(1)
+                        .is_some_and(|&(start, end)| start <= left && right <= end))
+            {
+                dsack = true;
+            }
+
+            for (left, right) in blocks {
+                // blocks for data that is not in flight are bogus or D-SACKs
+                if self.snd.una < left && left < right && right <= self.snd.max {
+                    self.retransmit_queue.sack(left, right, now);
+                }
+            }
+        }
+
+        dsack
+    }
+
+    /// RACK (RFC 8985), marks holes lost once a segment sent after them
+    /// was delivered and the reordering window has passed, then starts
+    /// recovery. The holes themselves are resent by `retransmit_lost`, the
+    /// reordering timer comes back for holes that are not overdue yet.
+    fn detect_loss(&mut self) {
+        if !self.sack_permitted {
+            return;
+        }
+
+        let now = Instant::now();
+        let in_recovery = self.snd.una < self.recover;
+        let (marked, deadline) =
+            self.retransmit_queue
+                .detect_loss(in_recovery, self.rtt.srtt(), now);
+        self.rack_timer = deadline;
+
+        if marked && !self.in_recovery {
+            self.enter_recovery();
+        }
+    }
+
+    /// Arms the probe timeout (RFC 8985 section 7.2) so losing the last
+    /// segments of a flight, which leaves nothing to trigger SACKs, is
+    /// noticed before the retransmission timer expires
+    fn schedule_loss_probe(&mut self) {
+        self.tlp_timer = None;
+
+        let in_recovery = self.snd.una < self.recover;
+        if !self.sack_permitted
+            || !self.state.is_synchronized()
+            || in_recovery
+            || self.tlp_end_seq.is_some()
+            || self.rack_timer.is_some()
+            || self.retransmit_queue.is_empty()
+        {
+            return;
+        }
+
+        // a lone segment may wait for a delayed ACK
+        let pto = match self.rtt.srtt() {
+            Some(srtt) if self.flight_size() <= self.mss => srtt * 2 + WORST_CASE_ACK_DELAY,
+            Some(srtt) => srtt * 2,
+            None => INITIAL_PTO,
+        };
+
+        let deadline = Instant::now() + pto;
+        self.tlp_timer = Some(self.rto_deadline.map_or(deadline, |rto| rto.min(deadline)));
+    }
+
+    /// Sends a tail loss probe, new data if the peer's window has room for
+    /// it and otherwise the last segment again. The peer's answer shows
+    /// RACK which segments are missing.
+    fn send_loss_probe(&mut self, now: Instant) {
+        let unsent = self.send_buffer.len() - self.bytes_in_flight();
+        let window_end = self.snd.una + self.snd.wnd;
+
+        if unsent > 0 && self.snd.nxt < window_end {
+            self.send_segment((window_end - self.snd.nxt) as usize);
+            self.tlp_retransmitted = false;
+        } else if let Some((seq, len)) = self.retransmit_queue.retransmit_last(now) {
+            self.resend(seq, len);
+            self.tlp_retransmitted = true;
+        } else {
+            return;
+        }
+
+        self.tlp_end_seq = Some(self.snd.max);
+        self.rto_deadline = Some(now + self.rtt.rto());
+    }
+
+    /// A retransmitted probe that is acknowledged without a D-SACK
+    /// repaired a real loss, which the congestion control has to hear
+    /// about even though no recovery ran (RFC 8985 section 7.4)
+    fn on_probe_ack(&mut self, ack: SeqNum, dsack: bool) {
+        let Some(end_seq) = self.tlp_end_seq else {
+            return;
+        };
+
+        if ack < end_seq {
+            return;
+        }
+
+        self.tlp_end_seq = None;
+        if self.tlp_retransmitted && !dsack {
+            self.congestion
+                .on_congestion_event(self.flight_size(), Instant::now());
+            self.congestion.on_recovery_end(self.pipe());
+        }
+    }
+
+    /// True for an ACK that repeats `SND.UNA` while data is outstanding
+    /// without carrying data or changing the window (RFC 5681 section 2).
+    /// Answers to zero window probes do not count.
+    fn is_duplicate_ack(&self, tcp: &TcpHeaderSlice<'_>) -> bool {
+        let shift = self.snd.wnd_shift;
+
+        SeqNum::from(tcp.ack_number()) == self.snd.una
+            && !self.retransmit_queue.is_empty()
+            && segment_len(tcp) == 0
+            && (tcp.window() as u32) << shift == self.snd.wnd
+    }
+
+    fn on_duplicate_ack(&mut self) {
+        self.dup_acks += 1;
+
+        let rate = self.retransmit_queue.take_rate_sample();
+        self.congestion.on_duplicate_ack(&AckInfo {
+            now: Instant::now(),
+            acked: 0,
+            in_flight: self.pipe(),
+            rtt: None,
+            in_recovery: self.in_recovery,
+            rate,
+        });
+
+        // RFC 6582, duplicates of data sent before the last recovery or
+        // timeout do not start another one. With SACK RACK decides instead.
+        if self.dup_acks == DUP_THRESH
+            && !self.sack_permitted
+            && !self.in_recovery
+            && self.recover < self.snd.una
+        {
+            self.retransmit_queue.mark_first_lost();
+            self.enter_recovery();
+        }
+    }
+
+    /// Fast retransmit, resends the oldest lost segment and lets the
+    /// congestion control react (RFC 5681 section 3.2)
+    fn enter_recovery(&mut self) {
+        self.congestion
+            .on_congestion_event(self.flight_size(), Instant::now());
+        self.in_recovery = true;
+        self.recover = self.snd.max;
+        self.tlp_timer = None;
+
+        self.retransmit_next_lost();
+    }
+
+    /// Resends segments the retransmission queue has marked lost as far as
+    /// the congestion window allows, without touching `SND.NXT`
+    fn retransmit_lost(&mut self) {
+        while self.pipe() < self.congestion.window() {
+            if !self.retransmit_next_lost() {
+                break;
+            }
+        }
+    }
+
+    /// Resends the oldest lost segment, returns false if there is none
+    fn retransmit_next_lost(&mut self) -> bool {
+        let now = Instant::now();
+        let Some((seq, len)) = self.retransmit_queue.retransmit_lost(now) else {
+            return false;
+        };
+
+        self.resend(seq, len);
+        true
+    }
+
+    /// Queues the `len` bytes of sequence space from `seq` again, the
+    /// retransmission queue already knows about them
+    fn resend(&mut self, seq: SeqNum, len: u32) {
+        let syn = !self.syn_acked as usize;
+        let fin = self.fin_seq == Some(seq + (len - 1));
+        let offset = (seq - self.snd.una) as usize - syn;
+
+        let segment = Segment {
+            fin,
+            data: self.send_buffer.read(offset, (len - fin as u32) as usize),
+            ..self.segment(seq)
+        };
+        self.send(segment);
+
+        if self.rto_deadline.is_none() {
+            self.rto_deadline = Some(Instant::now() + self.rtt.rto());
+        }
+    }
+
+    /// Retransmits everything from `SND.UNA` onwards once the retransmission
+    /// timer expires, giving up after `max_retries` attempts. With SACK only
+    /// the data the peer has not reported holding is resent. Also sends
+    /// delayed ACKs, zero window and keepalive probes and whatever pacing
+    /// held back, runs the RACK-TLP timers and ends TIME-WAIT or a
+    /// handshake that takes too long.
+    fn on_timer(&mut self, now: Instant) {
+        // only kept so the application can read what is left
+        if self.state == TcpState::Closed {
+            return;
+        }
+
+        if self
+            .time_wait_deadline
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.state = TcpState::Closed;
+            return;
+        }
+
+        // nobody knows about the connection yet, it is dropped quietly
+        if self
+            .handshake_deadline()
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.state = TcpState::Closed;
+            return;
+        }
+
+        if self.ack_timer.is_some_and(|deadline| deadline <= now) {
+            self.send_ack();
+        }
+
+        if self.persist_timer.is_some_and(|deadline| deadline <= now) {
+            self.persist_timer = None;
+            self.send_window_probe(now);
+        }
+
+        if self.keepalive_timer.is_some_and(|deadline| deadline <= now) {
+            self.keepalive_timer = None;
+            self.send_keepalive(now);
+        }
+
+        if self.pacing_timer.is_some_and(|deadline| deadline <= now) {
+            self.pacing_timer = None;
+            self.send_pending();
+        }
+
+        if self.cork_deadline.is_some_and(|deadline| deadline <= now) {
+            self.send_pending();
+            // still held back, by the windows this time, which the next ACK
+            // opens again
+            if self.cork_deadline.is_some_and(|deadline| deadline <= now) {
+                self.cork_deadline = None;
+            }
+        }
+
+        if self.rack_timer.is_some_and(|deadline| deadline <= now) {
+            self.rack_timer = None;
+            self.detect_loss();
+            self.retransmit_lost();
+        }
+
+        if self.tlp_timer.is_some_and(|deadline| deadline <= now) {
+            self.tlp_timer = None;
+            self.send_loss_probe(now);
+        }
+
+        if self.rto_deadline.is_none_or(|deadline| deadline > now) {
+            return;
+        }
+
+        self.retries += 1;
+        if self.retries > self.config.max_retries {
+            self.abort(TcpError::TimedOut);
+            return;
+        }
+
+        self.rtt.backoff();
+        self.rto_deadline = None;
+        self.rack_timer = None;
+        self.tlp_timer = None;
+        self.tlp_end_seq = None;
+
+        if self.syn_acked {
+            self.congestion.on_rto(self.flight_size());
+        }
+        // RFC 6582, duplicate ACKs for what was sent before the timeout
+        // must not start fast recovery
+        self.in_recovery = false;
+        self.dup_acks = 0;
+        self.recover = self.snd.max;
+
+        if self.sack_permitted && self.state.is_synchronized() && self.syn_acked {
+            self.retransmit_queue.on_timeout();
+            self.retransmit_lost();
+            self.send_pending();
+            return;
+        }
+
+        self.retransmit_queue.clear();
+        self.snd.nxt = self.snd.una;
+
+        match self.state {
+            TcpState::SynSent => self.send_syn(),
+            TcpState::SynRecieved if !self.syn_acked => self.send_syn_ack(),
+            _ => {}
+        }
+        self.send_pending();
+    }
+
+    /// when the handshake of a passively opened connection times out, only
+    /// while it is still in SYN-RECEIVED
+    fn handshake_deadline(&self) -> Option<Instant> {
+        self.handshake_deadline
+            .filter(|_| self.state == TcpState::SynRecieved)
+    }
+
+    /// earliest time `on_timer` has work to do
+    fn next_timeout(&self) -> Option<Instant> {
+        if self.state == TcpState::Closed {
+            return None;
+        }
+
+        [
+            self.rto_deadline,
+            self.pacing_timer,
+            self.cork_deadline,
+            self.rack_timer,
+            self.tlp_timer,
+            self.ack_timer,
+            self.persist_timer,
+            self.keepalive_timer,
+            self.time_wait_deadline,
+            self.handshake_deadline(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Sends `<SEQ=seq><CTL=RST>`
+    fn send_rst(&mut self, seq_number: SeqNum) {
+        // RFC 7323 recommends resets do not carry timestamps
+        let segment = Segment {
+            ack: false,
+            rst: true,
+            options: Vec::new(),
+            ack_number: SeqNum::default(),
+            ..self.segment(seq_number)
+        };
+        self.transmit(segment);
+    }
+
+    /// true once our FIN has been sent and acknowledged
+    fn fin_acked(&self) -> bool {
+        self.fin_seq.is_some_and(|fin| fin < self.snd.una)
+    }
+
+    fn abort(&mut self, error: TcpError) {
+        self.error = Some(error);
+        self.state = TcpState::Closed;
+    }
+
+    /// Aborts the connection and tells the peer with a reset
+    fn reset(&mut self) {
+        self.send_rst(self.snd.nxt);
+        self.abort(TcpError::ConnectionReset);
+    }
+
+    /// Active OPEN, sends our SYN
+    fn connect(&mut self) {
+        self.snd.una = self.snd.iss;
+        self.snd.max = self.snd.iss;
+        self.rcv.wnd = self.recv_buffer.window() as u32;
+
+        self.active_open = true;
+        self.send_syn();
+        self.state = TcpState::SynSent;
+    }
+
+    fn set_congestion_control(&mut self, mut congestion: Box<dyn CongestionControl>) {
+        congestion.set_mss(self.mss);
+        self.congestion = congestion;
+    }
+
+    /// User CLOSE call
+    fn close(&mut self) {
+        match self.state {
+            TcpState::Listen | TcpState::SynSent => self.state = TcpState::Closed,
+            // queued data can only go out once we know the peer's window
+            TcpState::SynRecieved if self.send_buffer.len() > 0 => self.fin_queued = true,
+            TcpState::SynRecieved | TcpState::Established => {
+                self.fin_queued = true;
+                self.state = TcpState::FinWait1;
+                self.send_pending();
+            }
+            TcpState::CloseWait => {
+                self.fin_queued = true;
+                self.state = TcpState::LastAck;
+                self.send_pending();
+            }
+            // already closing
+            TcpState::FinWait1
+            | TcpState::FinWait2
+            | TcpState::Closing
+            | TcpState::LastAck
+            | TcpState::TimeWait
+            | TcpState::Closed => {}
+        }
+    }
+
+    /// Half-closes the connection. Shutting down writing sends our FIN once
+    /// the queued data is out, like `close`. Shutting down reading throws
+    /// away what has not been read and everything that arrives later.
+    fn shutdown(&mut self, how: Shutdown) {
+        if matches!(how, Shutdown::Read | Shutdown::Both) {
+            self.read_shutdown = true;
+            self.readable = false;
+            self.recv_buffer.discard();
+
+            // tell the peer about the space that opened up
+            let window = self.recv_buffer.window() as u32;
+            if window > self.rcv.wnd && self.state.is_synchronized() {
+                self.rcv.wnd = window;
+                self.send_ack();
+            }
+        }
+
+        if matches!(how, Shutdown::Write | Shutdown::Both) {
+            self.close();
+        }
+    }
+
+    /// User SEND call, queues as much of `data` as the send buffer has room for
+    fn write(&mut self, data: &[u8]) -> Result<usize, TcpError> {
+        if self.fin_queued {
+            return Err(TcpError::Closing);
+        }
+
+        match self.state {
+            TcpState::SynSent
+            | TcpState::SynRecieved
+            | TcpState::Established
+            | TcpState::CloseWait => {}
+            TcpState::Closed | TcpState::Listen => return Err(TcpError::NotConnected),
+            _ => return Err(TcpError::Closing),
+        }
+
+        let len = self.send_buffer.write(data);
+        if len == 0 && !data.is_empty() {
+            return Err(TcpError::WouldBlock);
+        }
+
+        self.send_pending();
+        Ok(len)
+    }
+
+    fn on_packet(&mut self, tcp: &TcpHeaderSlice<'_>) {
+        match self.state {
+            TcpState::Closed => {}
+            TcpState::Listen => self.on_listen(tcp),
+            TcpState::SynSent => self.on_syn_sent(tcp),
+            _ => self.on_synchronized(tcp),
+        }
+
+        self.reset_keepalive();
+
+        // acks may have opened up the window, holes go out before new data
+        self.retransmit_lost();
+        self.send_pending();
+    }
+
+    fn on_listen(&mut self, tcp: &TcpHeaderSlice<'_>) {
+        if tcp.rst() {
+            return;
+        }
+
+        if tcp.ack() {
+            self.send_rst(SeqNum::from(tcp.ack_number()));
+            return;
+        }
+
+        if !tcp.syn() {
+            return;
+        }
+
+        self.snd.una = self.snd.iss;
+        self.snd.max = self.snd.iss;
+
+        self.rcv.irs = SeqNum::from(tcp.seq_number());
+        self.rcv.nxt = self.rcv.irs + 1;
+        self.rcv.wnd = self.recv_buffer.window() as u32;
+        self.on_syn_options(tcp);
+
+        self.send_syn_ack();
+        self.state = TcpState::SynRecieved;
+        self.handshake_deadline = Some(Instant::now() + self.config.handshake_timeout);
+    }
+
+    fn on_syn_sent(&mut self, tcp: &TcpHeaderSlice<'_>) {
+        let ack = SeqNum::from(tcp.ack_number());
+
+        if tcp.ack() && (ack <= self.snd.iss || self.snd.max < ack) {
+            if !tcp.rst() {
+                self.send_rst(ack);
+            }
+            return;
+        }
+
+        if tcp.rst() {
+            // without an acceptable ACK the reset could be for anything
+            if tcp.ack() {
+                self.abort(TcpError::ConnectionRefused);
+            }
+            return;
+        }
+
+        if !tcp.syn() {
+            return;
+        }
+
+        self.rcv.irs = SeqNum::from(tcp.seq_number());
+        self.rcv.nxt = self.rcv.irs + 1;
+        self.on_syn_options(tcp);
+
+        if tcp.ack() {
+            self.acknowledge(tcp);
+        }
+
+        self.set_send_window(tcp);
+
+        if self.syn_acked {
+            self.state = TcpState::Established;
+            self.send_ack();
+        } else {
+            // simultaneous open
+            self.state = TcpState::SynRecieved;
+            self.send_syn_ack();
+        }
+    }
+
+    fn on_synchronized(&mut self, tcp: &TcpHeaderSlice<'_>) {
+        let seq = SeqNum::from(tcp.seq_number());
+
+        if tcp.rst() {
+            // RFC 5961, only a reset at exactly RCV.NXT is believed. One
+            // elsewhere in the window might be a blind guess so the peer
+            // has to confirm it by answering a challenge ACK
+            if seq != self.rcv.nxt {
+                if self.is_acceptable(tcp) {
+                    self.send_challenge_ack();
+                }
+                return;
+            }
+
+            match self.state {
+                TcpState::SynRecieved if self.active_open => {
+                    self.abort(TcpError::ConnectionRefused)
+                }
+                // a passive open goes back to listening which
+                // for us means forgetting about the quad
+                TcpState::SynRecieved => self.state = TcpState::Closed,
+                TcpState::Established
+                | TcpState::FinWait1
+                | TcpState::FinWait2
+                | TcpState::CloseWait => self.abort(TcpError::ConnectionReset),
+                _ => self.state = TcpState::Closed,
+            }
+            return;
+        }
+
+        if tcp.syn() {
+            match self.state {
+                // the peer never got our SYN-ACK and sent its SYN again
+                TcpState::SynRecieved if seq == self.rcv.irs => self.send_syn_ack(),
+                TcpState::SynRecieved if !self.active_open && self.is_acceptable(tcp) => {
+                    self.state = TcpState::Closed
+                }
+                // RFC 5961, whatever the sequence number a SYN on a
+                // synchronized connection is never trusted
+                _ => self.send_challenge_ack(),
+            }
+            return;
+        }
+
+        // RFC 7323, once timestamps are on a segment without one is dropped
+        // and PAWS rejects segments older than the last one we accepted
+        let timestamps = segment_timestamps(tcp);
+        if self.timestamps_enabled {
+            let Some((tsval, _)) = timestamps else {
+                return;
+            };
+
+            if self.timestamps.is_old(tsval, Instant::now()) {
+                self.send_ack();
+                return;
+            }
+        }
+
+        // with a zero window nothing is acceptable, but the ACK of a
+        // segment at RCV.NXT must still be processed or we could never
+        // learn that the peer's window opened. Its data and FIN are dropped.
+        let acceptable = self.is_acceptable(tcp);
+        let zero_window_ack = self.rcv.wnd == 0 && seq == self.rcv.nxt;
+        if !acceptable && !zero_window_ack {
+            // our ACK of the peer's FIN was lost and it sent the FIN again
+            if self.state == TcpState::TimeWait && tcp.fin() {
+                self.enter_time_wait();
+            }
+            self.send_ack();
+            return;
+        }
+
+        // TS.Recent follows the segment that the ACK we last sent covers
+        if let Some((tsval, _)) = timestamps
+            && self.timestamps_enabled
+            && self.timestamps.is_current(tsval)
+            && seq <= self.last_ack_sent
+        {
+            self.timestamps.set_recent(tsval, Instant::now());
+        }
+
+        if !tcp.ack() {
+            return;
+        }
+
+        let ack = SeqNum::from(tcp.ack_number());
+
+        if self.state == TcpState::SynRecieved {
+            if !(self.snd.una < ack && ack <= self.snd.max) {
+                self.send_rst(ack);
+                return;
+            }
+
+            self.state = if self.fin_queued {
+                TcpState::FinWait1
+            } else {
+                TcpState::Established
+            };
+            self.set_send_window(tcp);
+        }
+
+        match self.state {
+            TcpState::Established
+            | TcpState::FinWait1
+            | TcpState::FinWait2
+            | TcpState::CloseWait
+            | TcpState::Closing => {
+                // RFC 5961, an ACK for something we have not sent or from
+                // before the largest window the peer could have seen may be
+                // a blind data injection
+                let oldest = self.snd.una - self.snd.max_wnd;
+                if self.snd.max < ack || ack < oldest {
+                    self.send_challenge_ack();
+                    return;
+                }
+
+                let dsack = self.on_sack(tcp);
+                self.on_probe_ack(ack, dsack);
+
+                let advanced = self.snd.una < ack;
+                if advanced {
+                    self.acknowledge(tcp);
+                } else if self.is_duplicate_ack(tcp) {
+                    self.on_duplicate_ack();
+                }
+
+                if self.snd.una <= ack {
+                    self.update_window(tcp);
+                }
+
+                if dsack {
+                    self.retransmit_queue.on_dsack(self.snd.una, self.snd.nxt);
+                }
+                self.detect_loss();
+
+                // the probe timer restarts whenever new data is acknowledged
+                if advanced {
+                    self.schedule_loss_probe();
+                }
+
+                if self.state == TcpState::FinWait1 && self.fin_acked() {
+                    self.state = TcpState::FinWait2;
+                }
+
+                if self.state == TcpState::Closing {
+                    if self.fin_acked() {
+                        self.enter_time_wait();
+                    }
+                    return;
+                }
+            }
+            TcpState::LastAck => {
+                if self.snd.una < ack && ack <= self.snd.max {
+                    self.acknowledge(tcp);
+                }
+
+                if self.fin_acked() {
+                    self.state = TcpState::Closed;
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        if !acceptable {
+            self.send_ack();
+            return;
+        }
+
+        if tcp.urg()
+            && matches!(
+                self.state,
+                TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+            )
+        {
+            let urgent = seq + tcp.urgent_pointer() as u32;
+            if self.rcv.up < urgent {
+                self.rcv.up = urgent;
+            }
+        }
+
+        // data that lands right at RCV.NXT without a hole after it
+        let mut in_order = seq == self.rcv.nxt && !self.recv_buffer.has_out_of_order();
+
+        if !tcp.data().is_empty() {
+            // once the peer has sent a FIN there should be no more data
+            if matches!(
+                self.state,
+                TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+            ) {
+                self.receive(tcp);
+            } else {
+                in_order = false;
+            }
+        }
+
+        // a FIN can only be processed once everything before it has arrived
+        let fin_seq = seq + tcp.data().len() as u32;
+        if !tcp.fin() || fin_seq != self.rcv.nxt {
+            if !tcp.data().is_empty() {
+                self.ack_data(tcp, in_order);
+            }
+            return;
+        }
+
+        self.rcv.nxt += 1;
+        self.send_ack();
+
+        match self.state {
+            TcpState::SynRecieved | TcpState::Established => self.state = TcpState::CloseWait,
+            TcpState::FinWait1 => {
+                if self.fin_acked() {
+                    self.enter_time_wait();
+                } else {
+                    self.state = TcpState::Closing;
+                }
+            }
+            TcpState::FinWait2 => self.enter_time_wait(),
+            _ => {}
+        }
+    }
+
+    /// Enters TIME-WAIT, or restarts it, for 2MSL so that any segment of
+    /// this connection still in the network is gone before the quad can
+    /// be reused, and a lost ACK of the peer's FIN can be sent again
+    fn enter_time_wait(&mut self) {
+        self.state = TcpState::TimeWait;
+        self.time_wait_deadline = Some(Instant::now() + self.config.msl * 2);
+    }
+
+    /// RFC 6191, a SYN for a quad in TIME-WAIT may start a new connection
+    /// if it can not be mistaken for a segment of the old one. Its
+    /// timestamp has to be newer than TS.Recent or, without timestamps,
+    /// its sequence number has to be past RCV.NXT (RFC 1122).
+    fn accepts_syn_in_time_wait(&self, tcp: &TcpHeaderSlice<'_>) -> bool {
+        if self.state != TcpState::TimeWait || !tcp.syn() || tcp.ack() || tcp.rst() {
+            return false;
+        }
+
+        let seq = SeqNum::from(tcp.seq_number());
+        match segment_timestamps(tcp) {
+            Some((tsval, _)) if self.timestamps_enabled && tsval != self.timestamps.recent() => {
+                self.timestamps.is_current(tsval)
+            }
+            _ => self.rcv.nxt < seq,
+        }
+    }
+
+    /// The segment acceptability test from RFC 9293 section 3.10.7.4
+    ///
+    /// | length | window | test                                          |
+    /// |--------|--------|-----------------------------------------------|
+    /// | 0      | 0      | SEG.SEQ = RCV.NXT                             |
+    /// | 0      | >0     | RCV.NXT =< SEG.SEQ < RCV.NXT+RCV.WND          |
+    /// | >0     | 0      | not acceptable                                |
+    /// | >0     | >0     | the first or the last byte is in the window   |
+    fn is_acceptable(&self, tcp: &TcpHeaderSlice<'_>) -> bool {
+        let seq = SeqNum::from(tcp.seq_number());
+        let wnd_end = self.rcv.nxt + self.rcv.wnd;
+        let in_window = |seq: SeqNum| self.rcv.nxt <= seq && seq < wnd_end;
+
+        match (segment_len(tcp), self.rcv.wnd) {
+            (0, 0) => seq == self.rcv.nxt,
+            (0, _) => in_window(seq),
+            (_, 0) => false,
+            (len, _) => in_window(seq) || in_window(seq + (len - 1)),
+        }
+    }
+
+    /// Hands the segment text to the receive buffer and advances `RCV.NXT`
+    /// over whatever became in order
+    fn receive(&mut self, tcp: &TcpHeaderSlice<'_>) {
+        let seq = SeqNum::from(tcp.seq_number());
+        let mut data = tcp.data();
+        let mut offset = (seq - self.rcv.nxt) as usize;
+
+        // trim off the part we already have
+        if seq < self.rcv.nxt {
+            let duplicate = (self.rcv.nxt - seq) as usize;
+            data = &data[duplicate.min(data.len())..];
+            offset = 0;
+        }
+
+        let advanced = self.recv_buffer.insert(offset, data);
+        if self.read_shutdown {
+            self.recv_buffer.discard();
+        } else if advanced > 0 {
+            self.readable = true;
+        }
+
+        self.rcv.nxt += advanced as u32;
+        self.rcv.wnd = self.recv_buffer.window() as u32;
+    }
+
+    /// User RECEIVE call. Returns `Ok(0)` once the peer has closed and
+    /// everything it sent has been read, or reading was shut down.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, TcpError> {
+        if self.read_shutdown {
+            return Ok(0);
+        }
+
+        if self.recv_buffer.is_empty() {
+            if self.state.fin_received() || self.state == TcpState::Closed {
+                self.eof_read = true;
+                return Ok(0);
+            }
+            return Err(TcpError::WouldBlock);
+        }
+
+        let len = self.recv_buffer.read(buf);
+
+        // only advertise the larger window once a useful amount of space
+        // opened up, otherwise every small read would send an update
+        // (receiver SWS avoidance, RFC 9293 section 3.8.6.2.2)
+        let window = self.recv_buffer.window() as u32;
+        let threshold = (self.recv_buffer.capacity() / 2).min(self.mss) as u32;
+        if window.saturating_sub(self.rcv.wnd) >= threshold {
+            self.rcv.wnd = window;
+            self.send_ack();
+        }
+
+        Ok(len)
+    }
+
+    /// Updates the send window if the segment is newer than the one
+    /// used for the last update, this prevents old segments from
+    /// shrinking the window
+    fn update_window(&mut self, tcp: &TcpHeaderSlice<'_>) {
+        let seq = SeqNum::from(tcp.seq_number());
+        let ack = SeqNum::from(tcp.ack_number());
+
+        if self.snd.wl1 < seq || (self.snd.wl1 == seq && self.snd.wl2 <= ack) {
+            self.set_send_window(tcp);
+        }
+    }
+
+    fn set_send_window(&mut self, tcp: &TcpHeaderSlice<'_>) {
+        // the window in a SYN is never scaled
+        let shift = if tcp.syn() { 0 } else { self.snd.wnd_shift };
+        self.snd.wnd = (tcp.window() as u32) << shift;
+        self.snd.wl1 = SeqNum::from(tcp.seq_number());
+        self.snd.wl2 = SeqNum::from(tcp.ack_number());
+        self.snd.max_wnd = self.snd.max_wnd.max(self.snd.wnd);
+
+        // the peer is still there, and once its window opens there is
+        // nothing left to probe
+        self.unanswered_probes = 0;
+        if self.snd.wnd > 0 {
+            self.persist_timer = None;
+            self.persist_backoff = 0;
+        }
+    }
+}
+
+/// Identifies a connection. `src` is the remote end and `dst` is our end
+/// since quads are built from incoming packets.
+#[derive(Hash, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Quad {
+    src_ip: Ipv4Addr,
+    src_port: u16,
+    dst_ip: Ipv4Addr,
+    dst_port: u16,
+}
+
+impl Quad {
+    fn from(ip: &Ipv4HeaderSlice<'_>, tcp: &TcpHeaderSlice<'_>) -> Self {
+        Self {
+            src_ip: ip.src_ip(),
+            src_port: tcp.src_port(),
+            dst_ip: ip.dst_ip(),
+            dst_port: tcp.dst_port(),
+        }
+    }
+
+    pub fn local(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.dst_ip, self.dst_port)
+    }
+
+    pub fn remote(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.src_ip, self.src_port)
+    }
+}
+
+/// A passive OPEN. Connections that completed the handshake wait in
+/// `accept_queue` until the application accepts them.
+#[derive(Debug)]
+struct Listener {
+    /// most connections that may wait to be accepted
+    backlog: usize,
+    accept_queue: VecDeque<Quad>,
+}
+
+impl Listener {
+    fn new(backlog: usize) -> Self {
+        Self {
+            backlog,
+            accept_queue: VecDeque::new(),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.accept_queue.len() >= self.backlog
+    }
+}
+
+/// The address of the listener a connection to `local` belongs to. One
+/// bound to the exact address wins over one on the unspecified address.
+fn find_listener(
+    listeners: &HashMap<SocketAddrV4, Listener>,
+    local: SocketAddrV4,
+) -> Option<SocketAddrV4> {
+    let wildcard = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, local.port());
+    [local, wildcard]
+        .into_iter()
+        .find(|address| listeners.contains_key(address))
+}
+
+pub struct TcpConnManager {
+    config: TcpConfig,
+    conns: HashMap<Quad, TcpConn>,
+    isn_generator: Box<dyn IsnGenerator>,
+    /// addresses that accept incoming connections
+    listeners: HashMap<SocketAddrV4, Listener>,
+    /// passively opened connections in SYN-RECEIVED
+    half_open: usize,
+    syn_cookies: SynCookies,
+    /// next local port to try for an outgoing connection
+    next_ephemeral_port: u16,
+    outgoing: VecDeque<Segment>,
+    events: VecDeque<TcpEvent>,
+}
+
+impl Default for TcpConnManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TcpConnManager {
+    pub fn new() -> Self {
+        Self::with_config(TcpConfig::default())
+    }
+
+    pub fn with_config(config: TcpConfig) -> Self {
+        Self {
+            config,
+            conns: HashMap::new(),
+            isn_generator: Box::new(Rfc6528Isn::new()),
+            listeners: HashMap::new(),
+            half_open: 0,
+            syn_cookies: SynCookies::new(),
+            next_ephemeral_port: EPHEMERAL_PORT_START,
+            outgoing: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn process_packet(&mut self, ip: &Ipv4HeaderSlice<'_>, tcp: &TcpHeaderSlice<'_>) {
+        let quad = Quad::from(ip, tcp);
+        let listener = find_listener(&self.listeners, quad.local());
+
+        // the old connection is finished, the SYN starts a new one below
+        if listener.is_some()
+            && self
+                .conns
+                .get(&quad)
+                .is_some_and(|connection| connection.accepts_syn_in_time_wait(tcp))
+        {
+            self.conns.remove(&quad);
+            self.events.push_back(TcpEvent::Closed(quad));
+        }
+
+        if !self.conns.contains_key(&quad) {
+            let Some(listener) = listener else {
+                // nobody is listening, this is the CLOSED state
+                self.outgoing.extend(Segment::reset_for(ip, tcp));
+                return;
+            };
+            let accept_queue_full = self.listeners[&listener].is_full();
+
+            let irs = SeqNum::from(tcp.seq_number());
+            let ack = SeqNum::from(tcp.ack_number());
+
+            if tcp.syn() && !tcp.ack() && !tcp.rst() {
+                // the peer retries its SYN, by then the application may
+                // have caught up
+                if accept_queue_full {
+                    return;
+                }
+
+                if self.half_open >= self.config.max_half_open {
+                    self.send_syn_cookie(ip, tcp);
+                    return;
+                }
+            }
+
+            // the final ACK of a handshake we answered with a cookie
+            let cookie_mss = if tcp.ack() && !tcp.syn() && !tcp.rst() {
+                self.syn_cookies.validate(&quad, irs - 1, ack - 1)
+            } else {
+                None
+            };
+
+            let connection = match cookie_mss {
+                Some(_) if accept_queue_full => return,
+                Some(mss) => {
+                    self.half_open += 1;
+                    TcpConn::from_cookie(quad, self.config.clone(), ack - 1, irs - 1, mss)
+                }
+                None if tcp.syn() && !tcp.ack() && !tcp.rst() => {
+                    let iss = SeqNum::from(self.isn_generator.generate(&quad));
+                    TcpConn::new(quad, self.config.clone(), iss)
+                }
+                // only a SYN starts a connection, anything else is answered
+                // the way the LISTEN state would without building one
+                None => {
+                    if tcp.ack() {
+                        self.outgoing.extend(Segment::reset_for(ip, tcp));
+                    }
+                    return;
+                }
+            };
+            self.conns.insert(quad, connection);
+        }
+
+        let Some(connection) = self.conns.get_mut(&quad) else {
+            return;
+        };
+        let state = connection.state;
+
+        // kept only until the application has read it, the quad is CLOSED
+        if state == TcpState::Closed {
+            self.outgoing.extend(Segment::reset_for(ip, tcp));
+            return;
+        }
+
+        // Like Linux, the final ACK of a handshake is dropped while the
+        // accept queue is full. The connection stays in SYN-RECEIVED and the
+        // peer repeats the ACK when our SYN-ACK is retransmitted.
+        if state == TcpState::SynRecieved
+            && !connection.active_open
+            && tcp.ack()
+            && !tcp.syn()
+            && !tcp.rst()
+            && listener.is_some_and(|local| self.listeners[&local].is_full())
+        {
+            return;
+        }
+
+        connection.on_packet(tcp);
+        self.after_update(quad, state);
+    }
+
+    /// Answers a SYN with a SYN cookie instead of creating a connection
+    fn send_syn_cookie(&mut self, ip: &Ipv4HeaderSlice<'_>, tcp: &TcpHeaderSlice<'_>) {
+        let quad = Quad::from(ip, tcp);
+        let irs = SeqNum::from(tcp.seq_number());
+
+        let peer_mss = tcp
+            .options_iter()
+            .find_map(|option| match option {
+                TcpOption::MaxSegmentSize(mss) => Some(mss),
+                _ => None,
+            })
+            .unwrap_or(DEFAULT_MSS as u16);
+
+        // the cookie holds what we may send, the SYN-ACK what we receive
+        let mss = peer_mss.min(self.config.mss);
+        let Some(iss) = self.syn_cookies.generate(&quad, irs, mss) else {
+            return;
+        };
+        self.outgoing
+            .push_back(Segment::syn_cookie_for(ip, tcp, iss, self.config.mss));
+    }
+
+    /// Replaces how initial sequence numbers are picked, tests can use this
+    /// to make sequence numbers deterministic
+    pub fn set_isn_generator(&mut self, generator: Box<dyn IsnGenerator>) {
+        self.isn_generator = generator;
+    }
+
+    /// Passive OPEN, accepts connections to `local`. The unspecified IP
+    /// listens on every local address, though a listener on the exact
+    /// address comes first. At most `backlog` connections wait to be
+    /// accepted, past that SYNs and the ACKs that would complete a
+    /// handshake are dropped. Segments to addresses nobody
+    /// listens on are answered with a reset.
+    pub fn listen(&mut self, local: SocketAddrV4, backlog: usize) -> Result<(), TcpError> {
+        if self.listeners.contains_key(&local) {
+            return Err(TcpError::AddrInUse);
+        }
+
+        self.listeners.insert(local, Listener::new(backlog));
+        Ok(())
+    }
+
+    /// Stops accepting connections to `local`. Connections still waiting
+    /// to be accepted are reset.
+    pub fn unlisten(&mut self, local: &SocketAddrV4) {
+        let Some(listener) = self.listeners.remove(local) else {
+            return;
+        };
+
+        for quad in listener.accept_queue {
+            let Some(connection) = self.conns.get_mut(&quad) else {
+                continue;
+            };
+            let state = connection.state;
+
+            connection.reset();
+            self.after_update(quad, state);
+        }
+    }
+
+    /// Takes the oldest connection waiting in the accept queue of the
+    /// listener on `local`, `TcpError::WouldBlock` if there is none
+    pub fn accept(&mut self, local: &SocketAddrV4) -> Result<Quad, TcpError> {
+        let listener = self
+            .listeners
+            .get_mut(local)
+            .ok_or(TcpError::NotConnected)?;
+
+        listener
+            .accept_queue
+            .pop_front()
+            .ok_or(TcpError::WouldBlock)
+    }
+
+    /// Active OPEN, sends a SYN to `remote` from an unused local port. The
+    /// returned quad identifies the connection, `TcpEvent::Established`
+    /// reports when the handshake completes.
+    pub fn connect(&mut self, remote: SocketAddrV4) -> Result<Quad, TcpError> {
+        let quad = self
+            .ephemeral_quad(remote)
+            .ok_or(TcpError::AddrNotAvailable)?;
+
+        let iss = SeqNum::from(self.isn_generator.generate(&quad));
+        let mut connection = TcpConn::new(quad, self.config.clone(), iss);
+        connection.connect();
+
+        self.conns.insert(quad, connection);
+        self.after_update(quad, TcpState::Closed);
+
+        Ok(quad)
+    }
+
+    /// Finds a local port that is not already connected to `remote`
+    fn ephemeral_quad(&mut self, remote: SocketAddrV4) -> Option<Quad> {
+        for _ in EPHEMERAL_PORT_START..=EPHEMERAL_PORT_END {
+            let port = self.next_ephemeral_port;
+            self.next_ephemeral_port = if port == EPHEMERAL_PORT_END {
+                EPHEMERAL_PORT_START
+            } else {
+                port + 1
+            };
+
+            let quad = Quad {
+                src_ip: *remote.ip(),
+                src_port: remote.port(),
+                dst_ip: self.config.local_ip,
+                dst_port: port,
+            };
+
+            if !self.conns.contains_key(&quad) {
+                return Some(quad);
+            }
+        }
+
+        None
+    }
+
+    /// User CLOSE call, we will not send any more data on this connection
+    pub fn close(&mut self, quad: &Quad) {
+        let Some(connection) = self.conns.get_mut(quad) else {
+            return;
+        };
+        let state = connection.state;
+
+        connection.close();
+        self.after_update(*quad, state);
+    }
+
+    /// Half-closes a connection. After `Shutdown::Write` the peer gets our
+    /// FIN once the queued data is out while its data can still be read.
+    /// After `Shutdown::Read` reads return `Ok(0)` and anything the peer
+    /// sends is discarded. `Shutdown::Both` does both.
+    pub fn shutdown(&mut self, quad: &Quad, how: Shutdown) -> Result<(), TcpError> {
+        let connection = self.conns.get_mut(quad).ok_or(TcpError::NotConnected)?;
+        let state = connection.state;
+
+        connection.shutdown(how);
+        self.after_update(*quad, state);
+        Ok(())
+    }
+
+    /// User RECEIVE call, copies in order data into `buf`. Returns `Ok(0)`
+    /// once the peer has closed and all of its data has been read.
+    pub fn read(&mut self, quad: &Quad, buf: &mut [u8]) -> Result<usize, TcpError> {
+        let connection = self.conns.get_mut(quad).ok_or(TcpError::NotConnected)?;
+        let state = connection.state;
+
+        let result = connection.read(buf);
+        self.after_update(*quad, state);
+
+        result
+    }
+
+    /// Replaces the congestion control algorithm of a connection, the one
+    /// in `TcpConfig::congestion_control` is used unless this is called
+    pub fn set_congestion_control(
+        &mut self,
+        quad: &Quad,
+        congestion: Box<dyn CongestionControl>,
+    ) -> Result<(), TcpError> {
+        let connection = self.conns.get_mut(quad).ok_or(TcpError::NotConnected)?;
+        connection.set_congestion_control(congestion);
+        Ok(())
+    }
+
+    /// Quick-ACK mode acknowledges every data segment as soon as it
+    /// arrives, for interactive traffic where delayed ACKs add latency
+    pub fn set_quick_ack(&mut self, quad: &Quad, quick_ack: bool) -> Result<(), TcpError> {
+        let connection = self.conns.get_mut(quad).ok_or(TcpError::NotConnected)?;
+        let state = connection.state;
+
+        connection.set_quick_ack(quick_ack);
+        self.after_update(*quad, state);
+        Ok(())
+    }
+
+    /// Turns keepalive on or off for a connection, `None` turns it off. A
+    /// connection whose peer stops answering the probes is aborted with
+    /// `TcpError::TimedOut`.
+    pub fn set_keepalive(
+        &mut self,
+        quad: &Quad,
+        keepalive: Option<Keepalive>,
+    ) -> Result<(), TcpError> {
+        let connection = self.conns.get_mut(quad).ok_or(TcpError::NotConnected)?;
+        connection.set_keepalive(keepalive);
+        Ok(())
+    }
+
+    /// Turns Nagle's algorithm off for a connection so small writes go out
+    /// right away, like `TCP_NODELAY`
+    pub fn set_nodelay(&mut self, quad: &Quad, nodelay: bool) -> Result<(), TcpError> {
+        let connection = self.conns.get_mut(quad).ok_or(TcpError::NotConnected)?;
+        let state = connection.state;
+
+        connection.set_nodelay(nodelay);
+        self.after_update(*quad, state);
+        Ok(())
+    }
+
+    /// Holds back partial segments until `uncork`, like setting `TCP_CORK`.
+    /// Full segments still go out as soon as they can, and like in Linux a
+    /// partial segment is sent anyway after 200 ms.
+    pub fn cork(&mut self, quad: &Quad) -> Result<(), TcpError> {
+        let connection = self.conns.get_mut(quad).ok_or(TcpError::NotConnected)?;
+        let state = connection.state;
+
+        connection.cork();
+        self.after_update(*quad, state);
+        Ok(())
+    }
+
+    /// Sends whatever was held back since `cork`
+    pub fn uncork(&mut self, quad: &Quad) -> Result<(), TcpError> {
+        let connection = self.conns.get_mut(quad).ok_or(TcpError::NotConnected)?;
+        let state = connection.state;
+
+        connection.uncork();
+        self.after_update(*quad, state);
+        Ok(())
+    }
+
+    /// The congestion control algorithm of a connection. Upcast it to
+    /// `&dyn Any` and downcast to e.g. `Cubic` to inspect its state.
+    pub fn congestion_control(&self, quad: &Quad) -> Option<&dyn CongestionControl> {
+        self.conns
+            .get(quad)
+            .map(|connection| connection.congestion.as_ref())
+    }
+
+    /// User SEND call, queues as much of `data` as fits in the send buffer
+    /// and returns how many bytes were taken
+    pub fn write(&mut self, quad: &Quad, data: &[u8]) -> Result<usize, TcpError> {
+        let connection = self.conns.get_mut(quad).ok_or(TcpError::NotConnected)?;
+        let state = connection.state;
+
+        let result = connection.write(data);
+        self.after_update(*quad, state);
+
+        result
+    }
+
+    /// Fires any timers that have expired
+    pub fn on_timer(&mut self) {
+        let now = Instant::now();
+        let quads: Vec<Quad> = self.conns.keys().copied().collect();
+
+        for quad in quads {
+            let Some(connection) = self.conns.get_mut(&quad) else {
+                continue;
+            };
+            let state = connection.state;
+
+            connection.on_timer(now);
+            self.after_update(quad, state);
+        }
+    }
+
+    /// Earliest time `on_timer` needs to be called, `None` if no
+    /// timers are running
+    pub fn next_timeout(&self) -> Option<Instant> {
+        self.conns.values().filter_map(TcpConn::next_timeout).min()
+    }
+
+    pub fn poll_transmit(&mut self) -> Option<Segment> {
+        self.outgoing.pop_front()
+    }
+
+    pub fn poll_event(&mut self) -> Option<TcpEvent> {
+        self.events.pop_front()
+    }
+
+    /// Collects the segments a connection queued and reports state
+    /// changes the application cares about
+    fn after_update(&mut self, quad: Quad, old_state: TcpState) {
+        let Some(connection) = self.conns.get_mut(&quad) else {
+            return;
+        };
+
+        // a passively opened connection waits in its listener's accept queue
+        if !old_state.is_synchronized() && connection.state.is_synchronized() {
+            if connection.active_open {
+                self.events.push_back(TcpEvent::Established(quad));
+            } else if let Some(local) = find_listener(&self.listeners, quad.local()) {
+                if let Some(listener) = self.listeners.get_mut(&local) {
+                    listener.accept_queue.push_back(quad);
+                }
+                self.events.push_back(TcpEvent::Acceptable(local));
+            } else {
+                // the listener went away during the handshake
+                connection.reset();
+            }
+        }
+
+        self.outgoing.extend(connection.outgoing.drain(..));
+
+        let state = connection.state;
+        // the application only knows about connections it opened or that
+        // have completed the handshake, and closed ones that are still
+        // around because it has not read everything
+        let known =
+            old_state.is_synchronized() || connection.active_open || old_state == TcpState::Closed;
+
+        let half_open = |state| state == TcpState::SynRecieved && !connection.active_open;
+        match (half_open(old_state), half_open(state)) {
+            (false, true) => self.half_open += 1,
+            (true, false) => self.half_open -= 1,
+            _ => {}
+        }
+
+        if connection.readable {
+            connection.readable = false;
+            self.events.push_back(TcpEvent::Readable(quad));
+        }
+
+        if !old_state.fin_received() && state.fin_received() {
+            self.events.push_back(TcpEvent::PeerClosed(quad));
+        }
+
+        // listen entries only exist while a SYN is being processed
+        if state == TcpState::Listen {
+            self.conns.remove(&quad);
+        } else if state == TcpState::Closed {
+            // After a graceful close what the peer sent before its FIN can
+            // still be read, the connection goes once the application has
+            // seen the end of the stream
+            let unread = !connection.recv_buffer.is_empty() || old_state == TcpState::Closed;
+            if known
+                && connection.error.is_none()
+                && !connection.read_shutdown
+                && !connection.eof_read
+                && unread
+            {
+                return;
+            }
+
+            let error = connection.error;
+            let queued = known && !connection.active_open;
+            self.conns.remove(&quad);
+
+            // it can not be accepted anymore and should not take up the backlog
+            if queued {
+                for listener in self.listeners.values_mut() {
+                    listener.accept_queue.retain(|&waiting| waiting != quad);
+                }
+            }
+
+            if known {
+                match error {
+                    Some(error) => self.events.push_back(TcpEvent::Aborted(quad, error)),
+                    None => self.events.push_back(TcpEvent::Closed(quad)),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCAL: SocketAddrV4 = SocketAddrV4::new(DEFAULT_LOCAL_IP, 7);
+    const PEER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 40000);
+    const ISS: u32 = 1000;
+    const PEER_ISS: u32 = 5000;
+    /// what the peer's receive buffer holds before the application on the
+    /// other end stops reading
+    const PEER_WINDOW: u16 = 300;
+
+    struct FixedIsn(u32);
+
+    impl IsnGenerator for FixedIsn {
+        fn generate(&mut self, _quad: &Quad) -> u32 {
+            self.0
+        }
+    }
+
+    fn peer_segment(seq: u32, ack: u32, window: u16) -> Segment {
+        Segment {
+            src: PEER,
+            dst: LOCAL,
+            seq_number: SeqNum::from(seq),
+            ack_number: SeqNum::from(ack),
+            ack: true,
+            rst: false,
+            syn: false,
+            fin: false,
+            window,
+            options: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    fn deliver(manager: &mut TcpConnManager, segment: &Segment) {
+        let mut buf = vec![0; 20 + segment.tcp_header().length()];
+        segment.to_packet().to_buf(&mut buf);
+
+        let ip = Ipv4HeaderSlice::from_buf(&buf).unwrap();
+        let tcp = TcpHeaderSlice::from_buf(ip.payload()).unwrap();
+        manager.process_packet(&ip, &tcp);
+    }
+
+    fn drain(manager: &mut TcpConnManager) -> Vec<Segment> {
+        std::iter::from_fn(|| manager.poll_transmit()).collect()
+    }
+
+    /// Runs a connection's timers as if it was `now`
+    fn fire(manager: &mut TcpConnManager, quad: Quad, now: Instant) {
+        let connection = manager.conns.get_mut(&quad).unwrap();
+        let state = connection.state;
+
+        connection.on_timer(now);
+        manager.after_update(quad, state);
+    }
+
+    /// Opens a connection to a peer that reads slowly. Its buffer fills up
+    /// with the first `PEER_WINDOW` bytes we send and it answers them with
+    /// a zero window while more data is waiting to go out.
+    fn zero_window(config: TcpConfig) -> (TcpConnManager, Quad) {
+        let (mut manager, quad) = established(config);
+
+        let written = manager.write(&quad, &[b'x'; 400]).unwrap();
+        assert_eq!(written, 400);
+        let sent: usize = drain(&mut manager)
+            .iter()
+            .map(|segment| segment.data.len())
+            .sum();
+        assert_eq!(sent, PEER_WINDOW as usize);
+
+        // everything arrived but the application has not read any of it
+        let end = ISS + 1 + PEER_WINDOW as u32;
+        deliver(&mut manager, &peer_segment(PEER_ISS + 1, end, 0));
+        assert!(drain(&mut manager).is_empty());
+
+        (manager, quad)
+    }
+
+    /// Accepts a connection from the peer, which advertises `PEER_WINDOW`
+    fn established(config: TcpConfig) -> (TcpConnManager, Quad) {
+        let mut manager = TcpConnManager::with_config(config);
+        manager.set_isn_generator(Box::new(FixedIsn(ISS)));
+        manager.listen(LOCAL, 1).unwrap();
+
+        let syn = Segment {
+            syn: true,
+            ack: false,
+            ack_number: SeqNum::default(),
+            ..peer_segment(PEER_ISS, 0, PEER_WINDOW)
+        };
+        deliver(&mut manager, &syn);
+        let syn_ack = drain(&mut manager);
+        assert!(syn_ack.len() == 1 && syn_ack[0].syn && syn_ack[0].ack);
+
+        deliver(
+            &mut manager,
+            &peer_segment(PEER_ISS + 1, ISS + 1, PEER_WINDOW),
+        );
+        let quad = manager.accept(&LOCAL).unwrap();
+        assert!(drain(&mut manager).is_empty());
+
+        (manager, quad)
+    }
+
+    /// Moves a connection to where 2^32 bytes have been acknowledged and
+    /// `SND.UNA` is back at the ISS
+    fn wrap_to_iss(manager: &mut TcpConnManager, quad: &Quad) {
+        let connection = manager.conns.get_mut(quad).unwrap();
+        connection.snd.iss = connection.snd.una;
+    }
+
+    #[test]
+    fn data_stays_readable_after_half_close_completes() {
+        let (mut manager, quad) = established(TcpConfig::default());
+
+        // an HTTP/1.0 style request, the peer sends it and half-closes
+        let request = Segment {
+            fin: true,
+            data: b"request".to_vec(),
+            ..peer_segment(PEER_ISS + 1, ISS + 1, PEER_WINDOW)
+        };
+        deliver(&mut manager, &request);
+
+        manager.write(&quad, b"reply").unwrap();
+        manager.shutdown(&quad, Shutdown::Write).unwrap();
+        let sent = drain(&mut manager);
+        assert!(sent.iter().any(|segment| segment.fin));
+
+        // the peer acknowledges our reply and FIN, LAST-ACK is over
+        let fin_end = ISS + 1 + 5 + 1;
+        deliver(
+            &mut manager,
+            &peer_segment(PEER_ISS + 9, fin_end, PEER_WINDOW),
+        );
+        assert_eq!(manager.conns[&quad].state, TcpState::Closed);
+
+        let mut buf = [0; 16];
+        assert_eq!(manager.read(&quad, &mut buf), Ok(7));
+        assert_eq!(&buf[..7], b"request");
+        assert!(manager.conns.contains_key(&quad));
+        assert!(
+            !std::iter::from_fn(|| manager.poll_event())
+                .any(|event| matches!(event, TcpEvent::Closed(_)))
+        );
+
+        // the end of the stream is read, only now does the connection go
+        assert_eq!(manager.read(&quad, &mut buf), Ok(0));
+        assert!(!manager.conns.contains_key(&quad));
+        assert!(matches!(
+            manager.poll_event(),
+            Some(TcpEvent::Closed(closed)) if closed == quad
+        ));
+        assert_eq!(manager.next_timeout(), None);
+    }
+
+    #[test]
+    fn timeout_after_sequence_space_wraps_to_iss_resends_data() {
+        let (mut manager, quad) = established(TcpConfig::default());
+        wrap_to_iss(&mut manager, &quad);
+        let una = ISS + 1;
+
+        let data: Vec<u8> = (0..200).collect();
+        manager.write(&quad, &data).unwrap();
+        assert_eq!(drain(&mut manager).len(), 1);
+
+        let deadline = manager.conns[&quad].rto_deadline.unwrap();
+        fire(&mut manager, quad, deadline);
+
+        let resent = drain(&mut manager);
+        assert_eq!(resent.len(), 1);
+        assert!(!resent[0].syn);
+        assert_eq!(u32::from(resent[0].seq_number), una);
+        assert_eq!(resent[0].data, data);
+
+        // the loss window from RFC 5681
+        let connection = &manager.conns[&quad];
+        assert_eq!(connection.congestion.window(), connection.mss);
+    }
+
+    #[test]
+    fn data_lines_up_after_sequence_space_wraps_to_iss() {
+        let (mut manager, quad) = established(TcpConfig::default());
+        wrap_to_iss(&mut manager, &quad);
+        let una = ISS + 1;
+
+        let data: Vec<u8> = (0..200).collect();
+        assert_eq!(manager.write(&quad, &data).unwrap(), 200);
+        let sent = drain(&mut manager);
+        assert_eq!(sent.len(), 1);
+        assert_eq!(u32::from(sent[0].seq_number), una);
+        assert_eq!(sent[0].data, data);
+
+        // half of it arrives, the rest goes out again from where it left off
+        deliver(
+            &mut manager,
+            &peer_segment(PEER_ISS + 1, una + 100, PEER_WINDOW),
+        );
+        assert_eq!(manager.conns[&quad].send_buffer.len(), 100);
+        assert_eq!(manager.conns[&quad].bytes_in_flight(), 100);
+
+        let connection = manager.conns.get_mut(&quad).unwrap();
+        let state = connection.state;
+        connection.resend(SeqNum::from(una + 100), 100);
+        manager.after_update(quad, state);
+        let resent = drain(&mut manager);
+        assert_eq!(resent.len(), 1);
+        assert_eq!(resent[0].data, data[100..]);
+    }
+
+    fn persist_timer(manager: &TcpConnManager, quad: &Quad) -> Option<Instant> {
+        manager.conns[quad].persist_timer
+    }
+
+    #[test]
+    fn zero_window_is_probed_with_one_byte() {
+        let (mut manager, quad) = zero_window(TcpConfig::default());
+        let deadline = persist_timer(&manager, &quad).expect("persist timer not started");
+
+        fire(&mut manager, quad, deadline);
+        let probes = drain(&mut manager);
+
+        assert_eq!(probes.len(), 1);
+        let probe = &probes[0];
+        assert_eq!(probe.data, [b'x']);
+        // the byte lies just past the closed window
+        assert_eq!(u32::from(probe.seq_number), ISS + 1 + PEER_WINDOW as u32);
+        assert!(manager.conns[&quad].retransmit_queue.is_empty());
+    }
+
+    #[test]
+    fn persist_interval_doubles_up_to_the_cap() {
+        let (mut manager, quad) = zero_window(TcpConfig::default());
+        let rto = manager.conns[&quad].rtt.rto();
+        let mut deadline = persist_timer(&manager, &quad).unwrap();
+
+        for probe in 1..=8 {
+            fire(&mut manager, quad, deadline);
+            assert_eq!(drain(&mut manager).len(), 1);
+
+            let next = persist_timer(&manager, &quad).unwrap();
+            let expected = (rto * (1 << probe)).min(MAX_PERSIST_INTERVAL);
+            assert_eq!(next - deadline, expected, "interval after probe {probe}");
+            assert_eq!(manager.conns[&quad].persist_backoff, probe);
+            deadline = next;
+        }
+        assert_eq!(
+            manager.conns[&quad].persist_interval(),
+            MAX_PERSIST_INTERVAL
+        );
+    }
+
+    #[test]
+    fn window_update_stops_probing() {
+        let (mut manager, quad) = zero_window(TcpConfig::default());
+        let end = ISS + 1 + PEER_WINDOW as u32;
+
+        for _ in 0..2 {
+            let deadline = persist_timer(&manager, &quad).unwrap();
+            fire(&mut manager, quad, deadline);
+            assert_eq!(drain(&mut manager).len(), 1);
+            // the peer is still not reading and throws the byte away
+            deliver(&mut manager, &peer_segment(PEER_ISS + 1, end, 0));
+        }
+        assert_eq!(manager.conns[&quad].persist_backoff, 2);
+
+        // the application on the other end finally reads
+        deliver(&mut manager, &peer_segment(PEER_ISS + 1, end, PEER_WINDOW));
+
+        let connection = &manager.conns[&quad];
+        assert_eq!(connection.persist_timer, None);
+        assert_eq!(connection.persist_backoff, 0);
+        assert_eq!(connection.unanswered_probes, 0);
+
+        let resumed = drain(&mut manager);
+        let sent: usize = resumed.iter().map(|segment| segment.data.len()).sum();
+        assert_eq!(sent, 100);
+        assert_eq!(u32::from(resumed[0].seq_number), end);
+    }
+
+    #[test]
+    fn unanswered_probes_time_out() {
+        let config = TcpConfig {
+            max_retries: 3,
+            ..TcpConfig::default()
+        };
+        let (mut manager, quad) = zero_window(config);
+        while manager.poll_event().is_some() {}
+
+        for _ in 0..3 {
+            let deadline = persist_timer(&manager, &quad).unwrap();
+            fire(&mut manager, quad, deadline);
+            assert_eq!(drain(&mut manager).len(), 1);
+        }
+        assert!(manager.poll_event().is_none());
+
+        let deadline = persist_timer(&manager, &quad).unwrap();
+        fire(&mut manager, quad, deadline);
+
+        assert!(!manager.conns.contains_key(&quad));
+        assert!(matches!(
+            manager.poll_event(),
+            Some(TcpEvent::Aborted(aborted, TcpError::TimedOut)) if aborted == quad
+        ));
+    }
+}
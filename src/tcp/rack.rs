@@ -0,0 +1,131 @@
+use std::time::{Duration, Instant};
+
+use crate::tcp::retransmit::DUP_THRESH;
+use crate::tcp::seq::SeqNum;
+
+/// recoveries a grown reordering window is kept for (RFC 8985 section 6.2)
+const REO_WND_PERSIST: u32 = 16;
+
+/// State for RACK loss detection from RFC 8985. Instead of counting
+/// duplicate ACKs a segment is lost once a segment sent after it was
+/// delivered and more than a round trip plus a reordering window has
+/// passed since it was sent.
+#[derive(Debug)]
+pub struct Rack {
+    /// send time of the most recently sent segment that was delivered
+    xmit_ts: Option<Instant>,
+    /// end of that segment, breaks ties between segments sent together
+    end_seq: SeqNum,
+    /// round trip time of that segment
+    rtt: Duration,
+    /// smallest round trip time seen
+    min_rtt: Option<Duration>,
+    /// highest sequence number delivered so far, forward-most ACK
+    fack: SeqNum,
+    /// a segment was delivered below `fack` without being retransmitted
+    reordering_seen: bool,
+    /// the reordering window in quarters of the minimum RTT, grows
+    /// every time a D-SACK shows we retransmitted for nothing
+    reo_wnd_mult: u32,
+    /// recoveries left before `reo_wnd_mult` goes back to 1
+    reo_wnd_persist: u32,
+    /// the window only grows once per round trip, until `SND.UNA`
+    /// passes this
+    dsack_round: Option<SeqNum>,
+}
+
+impl Rack {
+    pub fn new() -> Self {
+        Self {
+            xmit_ts: None,
+            end_seq: SeqNum::default(),
+            rtt: Duration::ZERO,
+            min_rtt: None,
+            fack: SeqNum::default(),
+            reordering_seen: false,
+            reo_wnd_mult: 1,
+            reo_wnd_persist: 0,
+            dsack_round: None,
+        }
+    }
+
+    /// true if the segment sent at `sent_at` ending at `end_seq` went out
+    /// before the most recently sent one that was delivered
+    pub fn sent_before(&self, sent_at: Instant, end_seq: SeqNum) -> bool {
+        self.xmit_ts.is_some_and(|xmit_ts| {
+            xmit_ts > sent_at || (xmit_ts == sent_at && end_seq < self.end_seq)
+        })
+    }
+
+    /// The segment ending at `end_seq` sent at `sent_at` was acknowledged
+    /// or SACKed
+    pub fn on_delivered(
+        &mut self,
+        end_seq: SeqNum,
+        sent_at: Instant,
+        retransmitted: bool,
+        now: Instant,
+    ) {
+        if end_seq > self.fack || self.xmit_ts.is_none() {
+            self.fack = end_seq;
+        } else if end_seq < self.fack && !retransmitted {
+            self.reordering_seen = true;
+        }
+
+        // an ACK quicker than any round trip so far is for the original
+        // transmission, not the retransmission we are timing
+        let rtt = now - sent_at;
+        if retransmitted && self.min_rtt.is_some_and(|min_rtt| rtt < min_rtt) {
+            return;
+        }
+
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |min_rtt| min_rtt.min(rtt)));
+        let newer = self.xmit_ts.is_none_or(|xmit_ts| {
+            sent_at > xmit_ts || (sent_at == xmit_ts && end_seq > self.end_seq)
+        });
+        if newer {
+            self.xmit_ts = Some(sent_at);
+            self.end_seq = end_seq;
+            self.rtt = rtt;
+        }
+    }
+
+    /// A D-SACK reported a segment arrived twice, widens the reordering
+    /// window at most once per round trip
+    pub fn on_dsack(&mut self, una: SeqNum, nxt: SeqNum) {
+        if self.dsack_round.is_some_and(|round| round <= una) {
+            self.dsack_round = None;
+        }
+
+        if self.dsack_round.is_none() {
+            self.dsack_round = Some(nxt);
+            self.reo_wnd_mult += 1;
+            self.reo_wnd_persist = REO_WND_PERSIST;
+        }
+    }
+
+    /// fast recovery or the recovery after a timeout finished
+    pub fn on_recovery_end(&mut self) {
+        self.reo_wnd_persist = self.reo_wnd_persist.saturating_sub(1);
+        if self.reo_wnd_persist == 0 {
+            self.reo_wnd_mult = 1;
+        }
+    }
+
+    /// How long to wait for reordered segments. Until reordering has been
+    /// seen segments are lost as soon as a later one arrives, during
+    /// recovery or once `DUP_THRESH` segments were SACKed.
+    pub fn reo_wnd(&self, in_recovery: bool, sacked: u32, srtt: Option<Duration>) -> Duration {
+        if !self.reordering_seen && (in_recovery || sacked >= DUP_THRESH) {
+            return Duration::ZERO;
+        }
+
+        let reo_wnd = self.min_rtt.unwrap_or_default() / 4 * self.reo_wnd_mult;
+        srtt.map_or(reo_wnd, |srtt| reo_wnd.min(srtt))
+    }
+
+    /// round trip time of the most recently sent segment that was delivered
+    pub fn rtt(&self) -> Duration {
+        self.rtt
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::{BTreeMap, VecDeque};
+
+/// Holds data received from the peer until the application reads it.
+/// Segments that arrive ahead of `RCV.NXT` are queued until the gap
+/// in front of them is filled.
+#[derive(Debug)]
+pub struct RecvBuffer {
+    /// in order bytes the application has not read yet
+    data: VecDeque<u8>,
+    /// out of order segments keyed by their offset in the stream
+    out_of_order: BTreeMap<u64, Vec<u8>>,
+    /// stream offset of `RCV.NXT`, sequence numbers wrap so we count
+    /// offsets instead to keep `out_of_order` sorted
+    nxt_offset: u64,
+    /// stream offset of the out of order segment that arrived last
+    last_out_of_order: Option<u64>,
+    /// most bytes held, memory is only taken as data arrives
+    capacity: usize,
+}
+
+impl RecvBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            data: VecDeque::new(),
+            out_of_order: BTreeMap::new(),
+            nxt_offset: 0,
+            last_out_of_order: None,
+            capacity,
+        }
+    }
+
+    /// free space in the buffer, this is what we advertise as our window
+    pub fn window(&self) -> usize {
+        self.capacity - self.data.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// true while segments are waiting for a hole in front of them
+    pub fn has_out_of_order(&self) -> bool {
+        !self.out_of_order.is_empty()
+    }
+
+    /// Stores `data` which starts `offset` bytes after `RCV.NXT`. Anything
+    /// past the window is dropped. Returns how many bytes became in order,
+    /// which is how far `RCV.NXT` should advance.
+    pub fn insert(&mut self, offset: usize, data: &[u8]) -> usize {
+        let window = self.window();
+        if offset >= window {
+            return 0;
+        }
+
+        let data = &data[..data.len().min(window - offset)];
+        if data.is_empty() {
+            return 0;
+        }
+
+        if offset > 0 {
+            let start = self.nxt_offset + offset as u64;
+            let queued = self.out_of_order.entry(start).or_default();
+            if queued.len() < data.len() {
+                *queued = data.to_vec();
+            }
+            self.last_out_of_order = Some(start);
+            return 0;
+        }
+
+        self.data.extend(data);
+        let mut advanced = data.len();
+        self.nxt_offset += data.len() as u64;
+
+        // pull in any queued segments the new data has caught up to
+        while let Some(entry) = self.out_of_order.first_entry() {
+            let start = *entry.key();
+            if start > self.nxt_offset {
+                break;
+            }
+
+            let segment = entry.remove();
+            let overlap = (self.nxt_offset - start) as usize;
+            if overlap >= segment.len() {
+                continue;
+            }
+
+            self.data.extend(&segment[overlap..]);
+            advanced += segment.len() - overlap;
+            self.nxt_offset += (segment.len() - overlap) as u64;
+        }
+
+        advanced
+    }
+
+    /// Out of order data as ranges of offsets after `RCV.NXT`, overlapping
+    /// segments are merged. The range holding the segment that arrived last
+    /// comes first as RFC 2018 asks for the first SACK block.
+    pub fn sack_blocks(&self) -> Vec<(usize, usize)> {
+        let mut blocks: Vec<(u64, u64)> = Vec::new();
+
+        for (&start, segment) in self.out_of_order.iter() {
+            let end = start + segment.len() as u64;
+            match blocks.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => blocks.push((start, end)),
+            }
+        }
+
+        let recent = self.last_out_of_order.and_then(|offset| {
+            blocks
+                .iter()
+                .position(|&(start, end)| start <= offset && offset < end)
+        });
+        if let Some(index) = recent {
+            let block = blocks.remove(index);
+            blocks.insert(0, block);
+        }
+
+        blocks
+            .into_iter()
+            .map(|(start, end)| {
+                (
+                    (start - self.nxt_offset) as usize,
+                    (end - self.nxt_offset) as usize,
+                )
+            })
+            .collect()
+    }
+
+    /// Copies in order data into `buf`, returns the number of bytes copied
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.data.len());
+
+        for (dst, src) in buf.iter_mut().zip(self.data.drain(..len)) {
+            *dst = src;
+        }
+
+        len
+    }
+
+    /// Drops the in order data nobody is going to read
+    pub fn discard(&mut self) {
+        self.data.clear();
+    }
+}
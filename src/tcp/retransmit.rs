@@ -0,0 +1,428 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::tcp::congestion::RateSample;
+use crate::tcp::rack::Rack;
+use crate::tcp::seq::SeqNum;
+
+const INITIAL_RTO: Duration = Duration::from_secs(1);
+const MIN_RTO: Duration = Duration::from_secs(1);
+const MAX_RTO: Duration = Duration::from_secs(60);
+/// clock granularity, the G in RFC 6298
+const GRANULARITY: Duration = Duration::from_millis(1);
+/// duplicate ACKs, or SACKed segments above a hole, before the hole is
+/// considered lost (RFC 5681, RFC 8985)
+pub const DUP_THRESH: u32 = 3;
+
+/// Smoothed round trip time and retransmission timeout as described
+/// in RFC 6298
+#[derive(Debug)]
+pub struct RttEstimator {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+}
+
+impl RttEstimator {
+    pub fn new() -> Self {
+        Self {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: INITIAL_RTO,
+        }
+    }
+
+    pub fn rto(&self) -> Duration {
+        self.rto
+    }
+
+    pub fn srtt(&self) -> Option<Duration> {
+        self.srtt
+    }
+
+    pub fn sample(&mut self, rtt: Duration) {
+        match self.srtt {
+            None => {
+                self.srtt = Some(rtt);
+                self.rttvar = rtt / 2;
+            }
+            Some(srtt) => {
+                // RTTVAR <- 3/4 * RTTVAR + 1/4 * |SRTT - R|
+                // SRTT <- 7/8 * SRTT + 1/8 * R
+                self.rttvar = self.rttvar * 3 / 4 + srtt.abs_diff(rtt) / 4;
+                self.srtt = Some(srtt * 7 / 8 + rtt / 8);
+            }
+        }
+
+        let srtt = self.srtt.unwrap_or(rtt);
+        self.rto = (srtt + GRANULARITY.max(self.rttvar * 4)).clamp(MIN_RTO, MAX_RTO);
+    }
+
+    /// Doubles the timeout after it expired
+    pub fn backoff(&mut self) {
+        self.rto = (self.rto * 2).min(MAX_RTO);
+    }
+}
+
+/// The connection's delivery counters at the time a segment was sent,
+/// compared against the counters when it is delivered to get a rate
+#[derive(Debug, Clone, Copy)]
+struct DeliveryState {
+    delivered: u64,
+    delivered_time: Instant,
+    first_sent_time: Instant,
+    app_limited: bool,
+}
+
+/// A segment that occupies sequence space and has not been
+/// acknowledged yet
+#[derive(Debug)]
+pub struct SentSegment {
+    pub seq: SeqNum,
+    /// sequence space taken up, including SYN and FIN
+    pub len: u32,
+    pub sent_at: Instant,
+    /// when the segment was first sent, kept across retransmissions
+    pub first_sent_at: Instant,
+    /// RTT samples are not taken from retransmitted segments (Karn's algorithm)
+    pub retransmitted: bool,
+    /// the peer reported holding the whole segment in a SACK block
+    pub sacked: bool,
+    /// waiting to be retransmitted
+    pub lost: bool,
+    delivery: DeliveryState,
+}
+
+impl SentSegment {
+    fn retransmit(&mut self, delivery: DeliveryState, now: Instant) -> (SeqNum, u32) {
+        self.lost = false;
+        self.retransmitted = true;
+        self.sent_at = now;
+        self.delivery = delivery;
+
+        (self.seq, self.len)
+    }
+}
+
+/// The part of a rate sample taken from the most recently sent segment
+/// an ACK delivered
+#[derive(Debug)]
+struct PendingSample {
+    prior_delivered: u64,
+    prior_time: Instant,
+    send_elapsed: Duration,
+    app_limited: bool,
+    rtt: Duration,
+}
+
+/// Delivery rate estimation as in draft-cheng-iccrg-delivery-rate-estimation
+#[derive(Debug)]
+struct DeliveryRate {
+    /// bytes delivered over the life of the connection
+    delivered: u64,
+    /// when `delivered` last changed
+    delivered_time: Instant,
+    /// send time of the most recently sent segment that was delivered
+    first_sent_time: Instant,
+    /// value of `delivered` once the data sent while the application had
+    /// nothing to send is delivered, zero when not app limited
+    app_limited: u64,
+    sample: Option<PendingSample>,
+}
+
+impl DeliveryRate {
+    fn new() -> Self {
+        let now = Instant::now();
+
+        Self {
+            delivered: 0,
+            delivered_time: now,
+            first_sent_time: now,
+            app_limited: 0,
+            sample: None,
+        }
+    }
+
+    fn snapshot(&self) -> DeliveryState {
+        DeliveryState {
+            delivered: self.delivered,
+            delivered_time: self.delivered_time,
+            first_sent_time: self.first_sent_time,
+            app_limited: self.app_limited != 0,
+        }
+    }
+
+    /// `bytes` of the segment sent at `sent_at` have been delivered
+    fn on_delivered(&mut self, state: &DeliveryState, sent_at: Instant, bytes: u32, now: Instant) {
+        self.delivered += bytes as u64;
+        self.delivered_time = now;
+
+        // the newest segment decides the sample
+        if self
+            .sample
+            .as_ref()
+            .is_none_or(|sample| state.delivered >= sample.prior_delivered)
+        {
+            self.sample = Some(PendingSample {
+                prior_delivered: state.delivered,
+                prior_time: state.delivered_time,
+                send_elapsed: sent_at - state.first_sent_time,
+                app_limited: state.app_limited,
+                rtt: now - sent_at,
+            });
+            self.first_sent_time = sent_at;
+        }
+    }
+}
+
+/// Segments in flight, oldest first. With SACK this doubles as the
+/// scoreboard from RFC 6675 and RACK decides which holes are lost. It
+/// also tracks how fast the segments are delivered.
+#[derive(Debug)]
+pub struct RetransmitQueue {
+    segments: VecDeque<SentSegment>,
+    rate: DeliveryRate,
+    rack: Rack,
+}
+
+impl RetransmitQueue {
+    pub fn new() -> Self {
+        Self {
+            segments: VecDeque::new(),
+            rate: DeliveryRate::new(),
+            rack: Rack::new(),
+        }
+    }
+
+    pub fn push(&mut self, seq: SeqNum, len: u32, now: Instant, retransmitted: bool) {
+        // nothing in flight, the next rate sample starts now
+        if self.segments.is_empty() {
+            self.rate.first_sent_time = now;
+            self.rate.delivered_time = now;
+        }
+
+        self.segments.push_back(SentSegment {
+            seq,
+            len,
+            sent_at: now,
+            first_sent_at: now,
+            retransmitted,
+            sacked: false,
+            lost: false,
+            delivery: self.rate.snapshot(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+    }
+
+    /// when the oldest segment still in flight was first sent
+    pub fn oldest_sent_at(&self) -> Option<Instant> {
+        self.segments.front().map(|segment| segment.first_sent_at)
+    }
+
+    /// Removes every segment covered by `ack` and returns a round trip
+    /// sample from the newest one that was only sent once
+    pub fn acknowledge(&mut self, ack: SeqNum, now: Instant) -> Option<Duration> {
+        let mut rtt = None;
+
+        while let Some(segment) = self.segments.front_mut() {
+            if ack <= segment.seq {
+                break;
+            }
+
+            let acked = ack - segment.seq;
+            if acked < segment.len {
+                // partially acknowledged, what is left stays in flight
+                if !segment.sacked {
+                    let sent_at = segment.sent_at;
+                    self.rate
+                        .on_delivered(&segment.delivery, sent_at, acked, now);
+                    self.rack
+                        .on_delivered(ack, sent_at, segment.retransmitted, now);
+                }
+                segment.seq = ack;
+                segment.len -= acked;
+                break;
+            }
+
+            if !segment.sacked {
+                self.rate
+                    .on_delivered(&segment.delivery, segment.sent_at, segment.len, now);
+                self.rack.on_delivered(
+                    segment.seq + segment.len,
+                    segment.sent_at,
+                    segment.retransmitted,
+                    now,
+                );
+            }
+
+            if !segment.retransmitted {
+                rtt = Some(now - segment.sent_at);
+            }
+            self.segments.pop_front();
+        }
+
+        rtt
+    }
+
+    /// Marks every segment that falls entirely inside the SACK block
+    /// from `left` up to but not including `right`
+    pub fn sack(&mut self, left: SeqNum, right: SeqNum, now: Instant) {
+        for segment in self.segments.iter_mut() {
+            if segment.sacked || !(left <= segment.seq && segment.seq + segment.len <= right) {
+                continue;
+            }
+
+            segment.sacked = true;
+            segment.lost = false;
+            self.rate
+                .on_delivered(&segment.delivery, segment.sent_at, segment.len, now);
+            self.rack.on_delivered(
+                segment.seq + segment.len,
+                segment.sent_at,
+                segment.retransmitted,
+                now,
+            );
+        }
+    }
+
+    /// The rate sample built from everything delivered since the last
+    /// call, `None` if nothing new was delivered
+    pub fn take_rate_sample(&mut self) -> Option<RateSample> {
+        let sample = self.rate.sample.take()?;
+
+        if self.rate.app_limited != 0 && self.rate.delivered > self.rate.app_limited {
+            self.rate.app_limited = 0;
+        }
+
+        let ack_elapsed = self.rate.delivered_time - sample.prior_time;
+        let interval = sample.send_elapsed.max(ack_elapsed);
+        if interval.is_zero() {
+            return None;
+        }
+
+        Some(RateSample {
+            delivered: self.rate.delivered - sample.prior_delivered,
+            interval,
+            prior_delivered: sample.prior_delivered,
+            app_limited: sample.app_limited,
+            rtt: sample.rtt,
+        })
+    }
+
+    /// The application has nothing more to send while there is room in
+    /// the window, samples until what is in flight now is delivered only
+    /// show how fast the application is
+    pub fn set_app_limited(&mut self, in_flight: u32) {
+        self.rate.app_limited = (self.rate.delivered + in_flight as u64).max(1);
+    }
+
+    /// RACK loss detection (RFC 8985 section 6.2). Marks every segment
+    /// sent before the newest delivered one that has waited longer than
+    /// its round trip plus the reordering window. Returns true if any
+    /// segment was newly marked, and when the rest become overdue.
+    pub fn detect_loss(
+        &mut self,
+        in_recovery: bool,
+        srtt: Option<Duration>,
+        now: Instant,
+    ) -> (bool, Option<Instant>) {
+        let sacked = self
+            .segments
+            .iter()
+            .filter(|segment| segment.sacked)
+            .count();
+        let reo_wnd = self.rack.reo_wnd(in_recovery, sacked as u32, srtt);
+        let mut marked = false;
+        let mut deadline = None;
+
+        for segment in self.segments.iter_mut() {
+            if segment.sacked
+                || segment.lost
+                || !self
+                    .rack
+                    .sent_before(segment.sent_at, segment.seq + segment.len)
+            {
+                continue;
+            }
+
+            let lost_at = segment.sent_at + self.rack.rtt() + reo_wnd;
+            if lost_at <= now {
+                segment.lost = true;
+                marked = true;
+            } else {
+                deadline = deadline.max(Some(lost_at));
+            }
+        }
+
+        (marked, deadline)
+    }
+
+    /// A D-SACK showed we retransmitted something that had arrived
+    pub fn on_dsack(&mut self, una: SeqNum, nxt: SeqNum) {
+        self.rack.on_dsack(una, nxt);
+    }
+
+    pub fn on_recovery_end(&mut self) {
+        self.rack.on_recovery_end();
+    }
+
+    /// Marks the oldest segment the peer has not SACKed as lost, for fast
+    /// retransmit and NewReno partial ACKs
+    pub fn mark_first_lost(&mut self) {
+        if let Some(segment) = self.segments.iter_mut().find(|segment| !segment.sacked) {
+            segment.lost = true;
+        }
+    }
+
+    /// Bytes still in the network, segments that were SACKed or are lost
+    /// and not retransmitted yet do not count (pipe in RFC 6675)
+    pub fn pipe(&self) -> u32 {
+        self.segments
+            .iter()
+            .filter(|segment| !segment.sacked && !segment.lost)
+            .map(|segment| segment.len)
+            .sum()
+    }
+
+    /// After a retransmission timeout everything that was not SACKed is
+    /// lost. The oldest segment is resent even if it was SACKed since the
+    /// receiver is allowed to throw SACKed data away (RFC 2018).
+    pub fn on_timeout(&mut self) {
+        if let Some(segment) = self.segments.front_mut() {
+            segment.sacked = false;
+        }
+
+        for segment in self.segments.iter_mut() {
+            segment.lost = !segment.sacked;
+        }
+    }
+
+    /// Takes the oldest lost segment and records that it is being
+    /// retransmitted, returns its sequence number and length
+    pub fn retransmit_lost(&mut self, now: Instant) -> Option<(SeqNum, u32)> {
+        let delivery = self.rate.snapshot();
+        let segment = self.segments.iter_mut().find(|segment| segment.lost)?;
+
+        Some(segment.retransmit(delivery, now))
+    }
+
+    /// Takes the newest segment the peer has not SACKed for a tail loss
+    /// probe, returns its sequence number and length
+    pub fn retransmit_last(&mut self, now: Instant) -> Option<(SeqNum, u32)> {
+        let delivery = self.rate.snapshot();
+        let segment = self
+            .segments
+            .iter_mut()
+            .rev()
+            .find(|segment| !segment.sacked)?;
+
+        Some(segment.retransmit(delivery, now))
+    }
+}
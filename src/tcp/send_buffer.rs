@@ -0,0 +1,42 @@
+use std::collections::VecDeque;
+
+/// Holds data the application wrote until the peer acknowledges it.
+/// The front of the buffer lines up with `SND.UNA`, so everything up to
+/// `SND.NXT` is in flight and the rest has not been sent yet.
+#[derive(Debug)]
+pub struct SendBuffer {
+    data: VecDeque<u8>,
+    /// most bytes held, memory is only taken as data is written
+    capacity: usize,
+}
+
+impl SendBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            data: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// number of bytes that are unacknowledged or unsent
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Appends as much of `data` as fits, returns the number of bytes taken
+    pub fn write(&mut self, data: &[u8]) -> usize {
+        let len = data.len().min(self.capacity - self.data.len());
+        self.data.extend(&data[..len]);
+        len
+    }
+
+    /// Copies `len` bytes starting `offset` bytes after `SND.UNA`
+    pub fn read(&self, offset: usize, len: usize) -> Vec<u8> {
+        self.data.range(offset..offset + len).copied().collect()
+    }
+
+    /// Drops `len` acknowledged bytes from the front of the buffer
+    pub fn acknowledge(&mut self, len: usize) {
+        self.data.drain(..len.min(self.data.len()));
+    }
+}
@@ -0,0 +1,117 @@
+use std::cmp::Ordering;
+use std::ops::{Add, AddAssign, Sub};
+
+/// A TCP sequence number. Arithmetic is modulo 2^32 and comparisons
+/// follow RFC 9293 section 3.4, `a < b` if `b` is less than 2^31 ahead
+/// of `a`. That makes the ordering wrap around and it is not transitive
+/// across the whole space, so only `PartialOrd` is implemented. Numbers
+/// exactly 2^31 apart are neither before nor after each other.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SeqNum(u32);
+
+impl From<u32> for SeqNum {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<SeqNum> for u32 {
+    fn from(value: SeqNum) -> Self {
+        value.0
+    }
+}
+
+impl PartialOrd for SeqNum {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match self.0.wrapping_sub(other.0) {
+            0x8000_0000 => None,
+            distance => Some((distance as i32).cmp(&0)),
+        }
+    }
+}
+
+impl Add<u32> for SeqNum {
+    type Output = SeqNum;
+
+    fn add(self, rhs: u32) -> Self::Output {
+        Self(self.0.wrapping_add(rhs))
+    }
+}
+
+impl AddAssign<u32> for SeqNum {
+    fn add_assign(&mut self, rhs: u32) {
+        self.0 = self.0.wrapping_add(rhs);
+    }
+}
+
+impl Sub<u32> for SeqNum {
+    type Output = SeqNum;
+
+    fn sub(self, rhs: u32) -> Self::Output {
+        Self(self.0.wrapping_sub(rhs))
+    }
+}
+
+/// distance from `rhs` forward to `self`
+impl Sub for SeqNum {
+    type Output = u32;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.0.wrapping_sub(rhs.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_nearby_numbers() {
+        let a = SeqNum::from(1000);
+        let b = SeqNum::from(2000);
+        assert!(a < b);
+        assert!(b > a);
+        assert!(a <= a);
+        assert!(a >= a);
+        assert_eq!(a.partial_cmp(&a), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn orders_across_wraparound() {
+        let before = SeqNum::from(u32::MAX - 10);
+        let after = before + 20;
+        assert_eq!(u32::from(after), 9);
+        assert!(before < after);
+        assert!(after > before);
+        assert_eq!(after - before, 20);
+    }
+
+    #[test]
+    fn half_the_space_ahead_is_still_after() {
+        let a = SeqNum::from(0);
+        let b = a + ((1 << 31) - 1);
+        assert!(a < b);
+        assert!(b > a);
+    }
+
+    #[test]
+    fn numbers_half_the_space_apart_are_unordered() {
+        let a = SeqNum::from(5);
+        let b = a + (1 << 31);
+        assert_eq!(a.partial_cmp(&b), None);
+        assert_eq!(b.partial_cmp(&a), None);
+        assert!(!a.lt(&b) && !b.lt(&a));
+        assert!(!a.gt(&b) && !b.gt(&a));
+    }
+
+    #[test]
+    fn arithmetic_wraps() {
+        let a = SeqNum::from(u32::MAX);
+        assert_eq!(u32::from(a + 1), 0);
+        assert_eq!(u32::from(SeqNum::from(0) - 1), u32::MAX);
+        let mut b = a;
+        b += 2;
+        assert_eq!(u32::from(b), 1);
+        assert_eq!(b - a, 2);
+    }
+}
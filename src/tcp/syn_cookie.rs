@@ -0,0 +1,100 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::{Duration, Instant};
+
+use crate::tcp::Quad;
+use crate::tcp::seq::SeqNum;
+
+/// MSS values a cookie can carry, the one sent is the largest that does
+/// not exceed the peer's. Peers asking for less than the smallest get no
+/// cookie.
+const MSS_TABLE: [u16; 8] = [216, 536, 1024, 1220, 1300, 1400, 1440, 1460];
+/// how often the counter in a cookie ticks
+const COUNTER_PERIOD: Duration = Duration::from_secs(64);
+/// ticks a cookie stays valid for after the one it was made in
+const MAX_COUNTER_AGE: u32 = 1;
+
+const COUNTER_SHIFT: u32 = 27;
+const COUNTER_MASK: u32 = 0x1f;
+const MSS_SHIFT: u32 = 24;
+const MSS_MASK: u32 = 0x7;
+const HASH_MASK: u32 = 0xff_ffff;
+
+/// SYN cookies as described in RFC 4987. Instead of keeping state for a
+/// half-open connection everything needed to rebuild it is encoded in the
+/// ISN of our SYN-ACK, and the peer hands it back as its ACK number minus
+/// one. From the top bit down the ISN holds a 5 bit counter that ticks
+/// every 64 seconds, a 3 bit index into `MSS_TABLE` and a 24 bit keyed
+/// hash of the quad, the peer's ISN, the counter and the index.
+///
+/// Options other than the MSS can not be encoded so a connection built
+/// from a cookie goes without window scaling, SACK and timestamps.
+pub struct SynCookies {
+    secret: RandomState,
+    epoch: Instant,
+    /// cookies are only checked for a while after one was sent, so ACKs
+    /// can not be used to guess one at any other time
+    last_sent: Option<Instant>,
+}
+
+impl SynCookies {
+    pub fn new() -> Self {
+        Self {
+            secret: RandomState::new(),
+            epoch: Instant::now(),
+            last_sent: None,
+        }
+    }
+
+    fn counter(&self) -> u32 {
+        (self.epoch.elapsed().as_secs() / COUNTER_PERIOD.as_secs()) as u32 & COUNTER_MASK
+    }
+
+    fn hash(&self, quad: &Quad, irs: SeqNum, counter: u32, index: u32) -> u32 {
+        let irs: u32 = irs.into();
+        self.secret.hash_one((quad, irs, counter, index)) as u32 & HASH_MASK
+    }
+
+    /// The ISN for a SYN-ACK answering a SYN with sequence number `irs`
+    /// from a peer that takes segments of up to `mss` bytes. `None` if that
+    /// is below every MSS a cookie can hold.
+    pub fn generate(&mut self, quad: &Quad, irs: SeqNum, mss: u16) -> Option<SeqNum> {
+        let index = MSS_TABLE.iter().rposition(|&entry| entry <= mss)? as u32;
+        let counter = self.counter();
+        self.last_sent = Some(Instant::now());
+
+        let cookie = (counter << COUNTER_SHIFT)
+            | (index << MSS_SHIFT)
+            | self.hash(quad, irs, counter, index);
+        Some(SeqNum::from(cookie))
+    }
+
+    /// Checks the cookie `iss` came back on an ACK for a SYN with sequence
+    /// number `irs`, returns the MSS it holds if it is ours and recent
+    pub fn validate(&self, quad: &Quad, irs: SeqNum, iss: SeqNum) -> Option<u16> {
+        let lifetime = COUNTER_PERIOD * (MAX_COUNTER_AGE + 1);
+        if self
+            .last_sent
+            .is_none_or(|last_sent| last_sent.elapsed() > lifetime)
+        {
+            return None;
+        }
+
+        let cookie: u32 = iss.into();
+        let counter = (cookie >> COUNTER_SHIFT) & COUNTER_MASK;
+        let index = (cookie >> MSS_SHIFT) & MSS_MASK;
+
+        let age = self.counter().wrapping_sub(counter) & COUNTER_MASK;
+        if age > MAX_COUNTER_AGE || cookie & HASH_MASK != self.hash(quad, irs, counter, index) {
+            return None;
+        }
+
+        Some(MSS_TABLE[index as usize])
+    }
+}
+
+impl Default for SynCookies {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+/// TS.Recent is no longer trusted for PAWS after this long without an
+/// update (RFC 7323 section 5.5)
+const TS_RECENT_MAX_AGE: Duration = Duration::from_secs(24 * 24 * 60 * 60);
+
+/// Timestamps option state from RFC 7323. Our clock ticks once a
+/// millisecond.
+#[derive(Debug)]
+pub struct Timestamps {
+    epoch: Instant,
+    /// added to the clock so TSval does not give away when the
+    /// connection was created
+    offset: u32,
+    /// TS.Recent, the peer's timestamp we echo back
+    recent: u32,
+    /// when `recent` was last updated
+    recent_age: Instant,
+}
+
+impl Timestamps {
+    pub fn new(offset: u32) -> Self {
+        let now = Instant::now();
+
+        Self {
+            epoch: now,
+            offset,
+            recent: 0,
+            recent_age: now,
+        }
+    }
+
+    /// our TSval at `now`
+    pub fn clock(&self, now: Instant) -> u32 {
+        let ticks = (now - self.epoch).as_millis() as u32;
+        self.offset.wrapping_add(ticks)
+    }
+
+    pub fn recent(&self) -> u32 {
+        self.recent
+    }
+
+    pub fn set_recent(&mut self, tsval: u32, now: Instant) {
+        self.recent = tsval;
+        self.recent_age = now;
+    }
+
+    /// true if `tsval` is newer than or equal to TS.Recent
+    pub fn is_current(&self, tsval: u32) -> bool {
+        tsval.wrapping_sub(self.recent) as i32 >= 0
+    }
+
+    /// PAWS, a segment carrying a TSval older than TS.Recent is an old
+    /// duplicate unless TS.Recent itself has gone stale
+    pub fn is_old(&self, tsval: u32, now: Instant) -> bool {
+        !self.is_current(tsval) && now - self.recent_age <= TS_RECENT_MAX_AGE
+    }
+
+    /// Round trip time of the segment whose TSval the peer echoed. An echo
+    /// from the future or from before `oldest_sent`, the first send of the
+    /// oldest unacknowledged segment, can not be for data in flight and
+    /// gives no sample (RFC 7323 section 4.1).
+    pub fn rtt(&self, echo_reply: u32, oldest_sent: Instant, now: Instant) -> Option<Duration> {
+        let clock = self.clock(now);
+        let ticks = clock.wrapping_sub(echo_reply);
+        let max_ticks = clock.wrapping_sub(self.clock(oldest_sent));
+        if ticks as i32 >= 0 && ticks <= max_ticks {
+            Some(Duration::from_millis(ticks as u64))
+        } else {
+            None
+        }
+    }
+}
@@ -1,4 +1,6 @@
 use crate::parse::{ipv4::Ipv4Packet, ipv4_header_slice::Ipv4HeaderSlice};
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
 use tun_tap::{Iface, Mode::Tun};
 
 const MTU: usize = 1504;
@@ -25,6 +27,39 @@ impl TunInterface {
         let transmit = Tx { iface: &self.iface };
         Ok((packet, transmit))
     }
+
+    /// Blocks until a packet is ready to be received or the timeout passes,
+    /// returns true if there is a packet. A timeout of `None` waits forever.
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<bool> {
+        let mut fd = libc::pollfd {
+            fd: self.iface.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // round up so we do not wake up just before a timer expires
+        let timeout = timeout.map_or(-1, |timeout| {
+            timeout.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32
+        });
+
+        let ready = unsafe { libc::poll(&mut fd, 1, timeout) };
+        if ready < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        Ok(ready > 0)
+    }
+
+    /// Handle for sending packets that are not a reply to a received one
+    pub fn tx(&self) -> Tx<'_> {
+        Tx { iface: &self.iface }
+    }
+}
+
+impl Default for TunInterface {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct Tx<'a> {